@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use default::DENSE_RAM_LIMIT;
+
+/// Assembles a little-endian long from the bytes at `addr` onwards, as
+/// given by `read_short`. Fails if reading any of them does, or if the long
+/// would run past the end of the address space.
+pub fn read_long_bytes<F>(addr: u32, mut read_short: F) -> Option<u32>
+        where F: FnMut(u32) -> Option<u8> {
+    let mut val = 0;
+
+    for i in 0..4 {
+        val |= (read_short(addr.checked_add(i)?)? as u32) << (8 * i);
+    }
+
+    Some(val)
+}
+
+/// The CPU's physical address space. Every access made through the `Mem`
+/// trait ends up here, so anything that can answer loads and stores (RAM,
+/// ROM, devices) can back the CPU's memory.
+///
+/// Reads return `None` and writes return `false` when nothing answers at
+/// the given address; the CPU turns that into a MEMORY interrupt.
+pub trait Bus {
+    /// Number of bytes of address space the bus covers.
+    fn size(&self) -> u64;
+
+    fn read_short(&mut self, addr: u32) -> Option<u8>;
+    fn write_short(&mut self, addr: u32, val: u8) -> bool;
+
+    /// Reads a little-endian long. The default reads it a byte at a time.
+    fn read_long(&mut self, addr: u32) -> Option<u32> {
+        read_long_bytes(addr, |a| self.read_short(a))
+    }
+
+    /// Writes a little-endian long. The default writes it a byte at a time,
+    /// so implementations which can fault part way through should override
+    /// it.
+    fn write_long(&mut self, addr: u32, val: u32) -> bool {
+        (0..4).all(|i| match addr.checked_add(i) {
+            Some(a) => self.write_short(a, (val >> (8 * i)) as u8),
+            None => false
+        })
+    }
+
+    /// Reads a byte without any side effects.
+    fn peek_short(&self, addr: u32) -> Option<u8>;
+
+    fn peek_long(&self, addr: u32) -> Option<u32> {
+        read_long_bytes(addr, |a| self.peek_short(a))
+    }
+
+    /// Would a read (or a write, if `write` is set) at `addr` succeed? Used
+    /// to check that an instruction can complete before performing any
+    /// access with side effects, so this must not have any itself. The
+    /// default says yes to anything that can be peeked.
+    fn accepts(&self, addr: u32, _write: bool) -> bool {
+        self.peek_short(addr).is_some()
+    }
+
+    /// Copies `data` in starting at `addr`, bypassing any write protection.
+    /// Returns false, leaving the bus untouched, if it does not fit.
+    fn load(&mut self, addr: u32, data: &[u8]) -> bool;
+
+    /// Zeroes all writable memory, for a cold reset.
+    fn clear(&mut self);
+}
+
+/// Creates `size` bytes of zeroed RAM starting at address 0. Sizes bigger
+/// than `DENSE_RAM_LIMIT` are backed by `SparseRam`, so host memory is only
+/// used for the parts the guest touches.
+pub fn new_ram(size: u64) -> Box<dyn Bus> {
+    if size > DENSE_RAM_LIMIT {
+        Box::new(SparseRam::new(size))
+    } else {
+        Box::new(Ram::new(size as u32))
+    }
+}
+
+/// Plain, flat RAM starting at address 0.
+pub struct Ram {
+    bytes: Vec<u8>
+}
+
+impl Ram {
+    pub fn new(size: u32) -> Ram {
+        Ram { bytes: vec![0; size as usize] }
+    }
+
+    /// Returns the range `addr..addr + len` as indices, if it is in bounds.
+    fn range(&self, addr: u32, len: usize) -> Option<::std::ops::Range<usize>> {
+        let start = addr as usize;
+
+        if start <= self.bytes.len() && len <= self.bytes.len() - start {
+            Some(start..start + len)
+        } else {
+            None
+        }
+    }
+}
+
+impl Bus for Ram {
+    fn size(&self) -> u64 {
+        self.bytes.len() as u64
+    }
+
+    fn read_short(&mut self, addr: u32) -> Option<u8> {
+        self.peek_short(addr)
+    }
+
+    fn write_short(&mut self, addr: u32, val: u8) -> bool {
+        match self.bytes.get_mut(addr as usize) {
+            Some(b) => {
+                *b = val;
+                true
+            }
+            None => false
+        }
+    }
+
+    fn read_long(&mut self, addr: u32) -> Option<u32> {
+        let r = self.range(addr, 4)?;
+        let b = &self.bytes[r];
+
+        Some((b[0] as u32) | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+    }
+
+    fn write_long(&mut self, addr: u32, val: u32) -> bool {
+        match self.range(addr, 4) {
+            Some(r) => {
+                for (i, b) in self.bytes[r].iter_mut().enumerate() {
+                    *b = (val >> (8 * i)) as u8;
+                }
+
+                true
+            }
+            None => false
+        }
+    }
+
+    fn peek_short(&self, addr: u32) -> Option<u8> {
+        self.bytes.get(addr as usize).cloned()
+    }
+
+    fn load(&mut self, addr: u32, data: &[u8]) -> bool {
+        match self.range(addr, data.len()) {
+            Some(r) => {
+                self.bytes[r].copy_from_slice(data);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn clear(&mut self) {
+        for b in self.bytes.iter_mut() {
+            *b = 0;
+        }
+    }
+}
+
+/// Size of the host allocations backing a `SparseRam`.
+const SPARSE_PAGE_SIZE: u32 = 4096;
+
+/// RAM starting at address 0 which only allocates host memory for a page
+/// the first time it is written to, so it can cover the whole 4 GiB
+/// address space. Pages which have never been written read as zero.
+pub struct SparseRam {
+    size: u64,
+    pages: HashMap<u32, Box<[u8]>>
+}
+
+impl SparseRam {
+    /// Creates `size` bytes of sparse RAM. `size` is capped at 4 GiB.
+    pub fn new(size: u64) -> SparseRam {
+        SparseRam {
+            size: ::std::cmp::min(size, 1 << 32),
+            pages: HashMap::new()
+        }
+    }
+
+    /// Number of pages which have been allocated so far.
+    pub fn allocated_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Is all of `addr..addr + len` in bounds?
+    fn in_bounds(&self, addr: u32, len: usize) -> bool {
+        addr as u64 + len as u64 <= self.size
+    }
+
+    fn page_mut(&mut self, addr: u32) -> &mut [u8] {
+        self.pages.entry(addr / SPARSE_PAGE_SIZE)
+                  .or_insert_with(|| vec![0; SPARSE_PAGE_SIZE as usize].into_boxed_slice())
+    }
+}
+
+impl Bus for SparseRam {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_short(&mut self, addr: u32) -> Option<u8> {
+        self.peek_short(addr)
+    }
+
+    fn write_short(&mut self, addr: u32, val: u8) -> bool {
+        if !self.in_bounds(addr, 1) {
+            return false;
+        }
+
+        self.page_mut(addr)[(addr % SPARSE_PAGE_SIZE) as usize] = val;
+        true
+    }
+
+    fn write_long(&mut self, addr: u32, val: u32) -> bool {
+        if !self.in_bounds(addr, 4) {
+            return false;
+        }
+
+        (0..4).all(|i| self.write_short(addr + i, (val >> (8 * i)) as u8))
+    }
+
+    fn peek_short(&self, addr: u32) -> Option<u8> {
+        if !self.in_bounds(addr, 1) {
+            return None;
+        }
+
+        match self.pages.get(&(addr / SPARSE_PAGE_SIZE)) {
+            Some(page) => Some(page[(addr % SPARSE_PAGE_SIZE) as usize]),
+            None => Some(0)
+        }
+    }
+
+    fn load(&mut self, addr: u32, data: &[u8]) -> bool {
+        if !self.in_bounds(addr, data.len()) {
+            return false;
+        }
+
+        let mut loaded = 0;
+
+        while loaded < data.len() {
+            let a = addr + loaded as u32;
+            let offset = (a % SPARSE_PAGE_SIZE) as usize;
+            let len = ::std::cmp::min(data.len() - loaded, SPARSE_PAGE_SIZE as usize - offset);
+
+            self.page_mut(a)[offset..offset + len].copy_from_slice(&data[loaded..loaded + len]);
+            loaded += len;
+        }
+
+        true
+    }
+
+    fn clear(&mut self) {
+        self.pages.clear();
+    }
+}
@@ -0,0 +1,191 @@
+//! A timing-only model of a cache hierarchy: split L1 instruction and data
+//! caches with an optional unified L2 behind them. It only counts hits,
+//! misses and evictions; memory contents always come from the bus.
+//!
+//! Caches are indexed by physical address. Writes allocate lines just like
+//! reads, and write-backs are not modelled.
+
+use std::fmt;
+
+use error::VmError;
+
+/// Which line of a set is replaced on a miss.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReplacementPolicy {
+    /// The least recently used line.
+    Lru,
+    /// The line which was filled longest ago.
+    Fifo,
+    /// A pseudo-random line, from a fixed seed so runs are repeatable.
+    Random
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Total capacity in bytes.
+    pub size: u32,
+    /// Lines per set.
+    pub associativity: u32,
+    /// Bytes per line.
+    pub line_size: u32,
+    pub policy: ReplacementPolicy
+}
+
+#[derive(Copy, Clone)]
+struct Line {
+    tag: u32,
+    /// When the line was last used (for LRU) or filled (for FIFO).
+    stamp: u64
+}
+
+pub struct Cache {
+    name: &'static str,
+    config: CacheConfig,
+    sets: Vec<Vec<Line>>,
+    clock: u64,
+    seed: u32,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64
+}
+
+impl Cache {
+    /// Creates an empty cache. The size, associativity and line size must
+    /// be powers of two, and the cache must hold at least one set.
+    pub fn new(name: &'static str, config: CacheConfig) -> Result<Cache, VmError> {
+        let &CacheConfig { size, associativity, line_size, .. } = &config;
+
+        let valid = size.is_power_of_two() && associativity.is_power_of_two()
+            && line_size.is_power_of_two()
+            && (line_size as u64) * (associativity as u64) <= size as u64;
+
+        if !valid {
+            return Err(VmError::InvalidCache { size, associativity, line_size });
+        }
+
+        let num_sets = size / line_size / associativity;
+
+        Ok(Cache {
+            name,
+            config,
+            sets: vec![Vec::with_capacity(associativity as usize); num_sets as usize],
+            clock: 0,
+            seed: 0x2545_F491,
+            hits: 0,
+            misses: 0,
+            evictions: 0
+        })
+    }
+
+    /// Looks up the line holding `addr`, filling it on a miss. Returns
+    /// whether it hit.
+    pub fn access(&mut self, addr: u32) -> bool {
+        let line_addr = addr / self.config.line_size;
+        let num_sets = self.sets.len() as u32;
+        let tag = line_addr / num_sets;
+
+        self.clock += 1;
+        let clock = self.clock;
+        let policy = self.config.policy;
+        let set = &mut self.sets[(line_addr % num_sets) as usize];
+
+        if let Some(line) = set.iter_mut().find(|l| l.tag == tag) {
+            if policy == ReplacementPolicy::Lru {
+                line.stamp = clock;
+            }
+
+            self.hits += 1;
+            return true;
+        }
+
+        self.misses += 1;
+        let new = Line { tag, stamp: clock };
+
+        if set.len() < self.config.associativity as usize {
+            set.push(new);
+            return false;
+        }
+
+        let victim = match policy {
+            ReplacementPolicy::Lru | ReplacementPolicy::Fifo =>
+                (0..set.len()).min_by_key(|&i| set[i].stamp).unwrap(),
+            ReplacementPolicy::Random => {
+                // xorshift32
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 17;
+                self.seed ^= self.seed << 5;
+                self.seed as usize % set.len()
+            }
+        };
+
+        set[victim] = new;
+        self.evictions += 1;
+        false
+    }
+}
+
+impl fmt::Display for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.hits + self.misses;
+        let rate = if total == 0 { 0.0 } else { self.hits as f64 * 100.0 / total as f64 };
+
+        write!(f, "{} ({} bytes, {}-way, {}-byte lines, {:?}): {} hits, {} misses, {} evictions \
+                   ({:.2}% hit rate)",
+               self.name, self.config.size, self.config.associativity, self.config.line_size,
+               self.config.policy, self.hits, self.misses, self.evictions, rate)
+    }
+}
+
+/// Split L1 caches, backed by an optional unified L2.
+pub struct CacheHierarchy {
+    pub l1i: Cache,
+    pub l1d: Cache,
+    pub l2: Option<Cache>
+}
+
+impl CacheHierarchy {
+    pub fn new(l1i: CacheConfig, l1d: CacheConfig, l2: Option<CacheConfig>)
+            -> Result<CacheHierarchy, VmError> {
+        Ok(CacheHierarchy {
+            l1i: Cache::new("L1I", l1i)?,
+            l1d: Cache::new("L1D", l1d)?,
+            l2: match l2 {
+                Some(config) => Some(Cache::new("L2", config)?),
+                None => None
+            }
+        })
+    }
+
+    /// Runs a `width`-byte access at physical address `pa` through the
+    /// hierarchy, touching every line it covers.
+    pub fn access(&mut self, pa: u32, width: u32, fetch: bool) {
+        let l1 = if fetch { &mut self.l1i } else { &mut self.l1d };
+        let line_size = l1.config.line_size as u64;
+        let last = pa as u64 + width as u64 - 1;
+        let mut line = pa as u64 / line_size;
+
+        while line <= last / line_size {
+            let addr = (line * line_size) as u32;
+
+            if !l1.access(addr) {
+                if let Some(ref mut l2) = self.l2 {
+                    l2.access(addr);
+                }
+            }
+
+            line += 1;
+        }
+    }
+}
+
+impl fmt::Display for CacheHierarchy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\n{}", self.l1i, self.l1d)?;
+
+        if let Some(ref l2) = self.l2 {
+            write!(f, "\n{}", l2)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,15 +1,25 @@
 use std::collections::VecDeque;
+use std::fmt;
 use std::fs::File;
 use std::io::Read;
+use std::time::{Duration, Instant};
 use wrapping_util::WrappingIncrement;
 
-use debug::*;
+use default::{MIN_MEM_SIZE, DEFAULT_TLB_SIZE};
+use error::VmError;
 
 use operation::{Operation, OperandParse};
 use interrupt::Interrupt;
-use mem::Mem;
+use mem::{Mem, AlignmentPolicy};
 use execute::Execute;
 use flag::{Flag, EXTERNAL_FLAG};
+use reset::{Reset, ResetKind, PowerOnState};
+use bus::{Bus, new_ram};
+use tlb::Tlb;
+use watch::{Watchpoint, WatchHit};
+use trace::TraceWriter;
+use cache::CacheHierarchy;
+use port::PortBus;
 
 pub struct Cpu {
     /// General-purpose registers r0-r15.
@@ -30,26 +40,135 @@ pub struct Cpu {
     /// Fault address register.
     pub rf: u32,
 
-    /// CPU's memory.
-    pub mem: Vec<u8>,
+    /// CPU's physical address space.
+    pub bus: Box<dyn Bus>,
+    /// Cache of page table walks.
+    pub tlb: Tlb,
+    /// Devices reachable through IN and OUT.
+    pub ports: PortBus,
 
     /// If a MEMORY interrupt occurred, this will hold the value
     /// of the address for which the interrupt was raised.
     pub mem_interrupt_address: Option<u32>,
+    /// Error code pushed along with a MEMORY interrupt raised while
+    /// paging is enabled. See `mmu` for the codes.
+    pub mem_fault_code: Option<u32>,
+    /// The pending MEMORY interrupt was caused by a misaligned access, and
+    /// is delivered as ALIGNMENT instead.
+    pub mem_misaligned: bool,
     /// Has an INSTRUCTION interrupt occurred?
     pub instr_interrupt: bool,
     /// Has a PROTECT interrupt occurred?
     pub protect_interrupt: bool,
-    /// Queue holding other scheduled general interrupts.
-    pub interrupt_queue: VecDeque<u8>
+    /// Queue holding other scheduled general interrupts. These are
+    /// delivered before any from the interrupt controller.
+    pub interrupt_queue: VecDeque<u8>,
+
+    /// Number of instructions which have run to completion.
+    pub instructions_retired: u64,
+    /// Where to log every memory access, if anywhere.
+    pub trace: Option<TraceWriter>,
+    /// Cache model to run every memory access through, if any.
+    pub caches: Option<CacheHierarchy>,
+
+    /// Address of the instruction being executed.
+    pub instruction_start: u32,
+    /// Ranges of memory to stop on accesses to.
+    pub watchpoints: Vec<Watchpoint>,
+    /// The first watched access made during the current step.
+    pub watch_hit: Option<WatchHit>,
+
+    /// Exit status (the value of r0) given by a HLT executed in kernel
+    /// mode, or `None` if the CPU has not halted.
+    pub halted: Option<u32>,
+
+    /// State the CPU is put in by a reset.
+    pub power_on: PowerOnState,
+    /// What to do when delivering a DOUBLE_FAULT interrupt faults.
+    pub triple_fault_action: TripleFaultAction,
+    /// What to do about misaligned long accesses.
+    pub alignment_policy: AlignmentPolicy
+}
+
+/// What the CPU does on a triple fault.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TripleFaultAction {
+    /// Reset the CPU and keep running.
+    Reset,
+    /// Stop, returning `VmError::TripleFault` from `Cpu::step`.
+    Shutdown
+}
+
+/// What happened during a single call to `Cpu::step`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction ran to completion without interrupting.
+    Executed,
+    /// Control was transferred to the handler for this interrupt vector.
+    Interrupt(u8),
+    /// Delivering this interrupt vector faulted, so control was transferred
+    /// to the DOUBLE_FAULT handler instead, with the vector as its code.
+    DoubleFault(u8),
+    /// The CPU was reset, by the guest or by a triple fault.
+    Reset(ResetKind),
+    /// The CPU executed HLT in kernel mode with this exit status and will
+    /// not execute any more instructions.
+    Halted(u32),
+    /// An instruction ran to completion and made an access covered by a
+    /// watchpoint.
+    Watchpoint(WatchHit)
 }
 
+/// Why `Cpu::run` returned.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// HLT was executed with this exit status.
+    Halted(u32),
+    /// The requested number of instructions was stepped.
+    InstructionLimit,
+    /// The wall-clock timeout expired.
+    Timeout,
+    /// A watchpoint was hit.
+    Watchpoint(WatchHit)
+}
+
+/// Limits on how long `Cpu::run_with_limits` may keep stepping.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RunLimits {
+    /// Maximum number of steps to take.
+    pub max_instructions: Option<u64>,
+    /// Maximum wall-clock time to run for.
+    pub timeout: Option<Duration>
+}
+
+/// How many steps to take between checks of the wall clock.
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
 impl Cpu {
-    pub fn new(kernel_file: &str, mem_size: u32) -> Cpu {
-        if mem_size < 128 {
-            fatal!("There should be more than 128 bytes of memory!");
+    /// Creates a CPU with `mem_size` bytes of memory and loads `kernel_file`
+    /// at address 0.
+    pub fn new(kernel_file: &str, mem_size: u64) -> Result<Cpu, VmError> {
+        if mem_size < MIN_MEM_SIZE as u64 {
+            return Err(VmError::MemoryTooSmall(mem_size));
         }
 
+        let mut cpu = Cpu::with_memory(mem_size);
+        cpu.load_file(kernel_file, 0)?;
+
+        Ok(cpu)
+    }
+
+    /// Creates a CPU with `mem_size` bytes of zeroed RAM, up to 4 GiB, in
+    /// the default power-on state. RAM bigger than `DENSE_RAM_LIMIT` is
+    /// allocated lazily. Nothing is loaded; use `load_image` to put a
+    /// program in memory.
+    pub fn with_memory(mem_size: u64) -> Cpu {
+        Cpu::with_bus(new_ram(mem_size))
+    }
+
+    /// Creates a CPU in the default power-on state whose memory accesses all
+    /// go to `bus`.
+    pub fn with_bus(bus: Box<dyn Bus>) -> Cpu {
         let mut cpu = Cpu {
             reg: [0; 16],
             rflags: 0,
@@ -59,92 +178,191 @@ impl Cpu {
             rks: 0,
             rkt: 0,
             rf: 0,
-            mem: vec![0; mem_size as usize],
+            bus,
+            tlb: Tlb::new(DEFAULT_TLB_SIZE),
+            ports: PortBus::new(),
             mem_interrupt_address: None,
+            mem_fault_code: None,
+            mem_misaligned: false,
             instr_interrupt: false,
             protect_interrupt: false,
-            interrupt_queue: VecDeque::new()
+            interrupt_queue: VecDeque::new(),
+            instructions_retired: 0,
+            trace: None,
+            caches: None,
+            instruction_start: 0,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            halted: None,
+            power_on: PowerOnState::default(),
+            triple_fault_action: TripleFaultAction::Shutdown,
+            alignment_policy: AlignmentPolicy::Allow
         };
 
-        let mut file = File::open(kernel_file).unwrap_or_die(INVALID_FILE);
-
-        // Load the file into a temporary vector
-        let mut v = Vec::new();
-        file.read_to_end(&mut v).unwrap_or_die(CANNOT_READ_FILE);
+        cpu.reset(ResetKind::Cold);
+        cpu
+    }
 
-        if v.len() > mem_size as usize {
-            fatal!("Kernel file too large to fit in memory!");
+    /// Copies `image` into memory starting at `addr`. Memory is left untouched
+    /// if the image does not fit.
+    pub fn load_image(&mut self, image: &[u8], addr: u32) -> Result<(), VmError> {
+        if !self.bus.load(addr, image) {
+            return Err(VmError::ImageTooLarge { addr, size: image.len() });
         }
 
-        // Then bitwise-copy the vector into the CPU's memory.
-        for (i, b) in v.into_iter().enumerate() {
-            debug!("Mem init: Copying byte 0x{:X} to {}", b, i);
-            cpu.mem[i] = b;
-        }
+        debug!("Mem init: Copied {} bytes to 0x{:X}", image.len(), addr);
+        Ok(())
+    }
 
-        cpu
+    /// Reads the file at `path` and loads it into memory at `addr`.
+    pub fn load_file(&mut self, path: &str, addr: u32) -> Result<(), VmError> {
+        let mut v = Vec::new();
+        File::open(path)?.read_to_end(&mut v)?;
+
+        self.load_image(&v, addr)
     }
 
-    pub fn boot(mut self) -> ! {
+    /// Sets the address of the first instruction to execute.
+    pub fn set_entry_point(&mut self, addr: u32) {
+        self.rp = addr;
+    }
+
+    /// Runs the CPU until it halts, returning the exit status of the HLT.
+    /// Watchpoints are ignored.
+    pub fn boot(&mut self) -> Result<u32, VmError> {
         loop {
-            // Save the old rp, if we interrupt.
-            let rp = self.rp;
-            let opcode = self.mem_get_short(rp);
-
-            // Handle MEMORY interrupt retrieving opcode.
-            if self.has_memory_interrupt() {
-                debug!("Memory interrupt while reading opcode.");
-                self.trigger_memory_interrupt();
-                continue;
+            if let StopReason::Halted(status) = self.run(None)? {
+                return Ok(status);
             }
+        }
+    }
 
-            let operation;
+    /// Steps the CPU until it halts, or until `max_instructions` steps
+    /// have been taken.
+    pub fn run(&mut self, max_instructions: Option<u64>) -> Result<StopReason, VmError> {
+        self.run_with_limits(RunLimits { max_instructions, timeout: None })
+    }
+
+    /// Steps the CPU until it halts or one of `limits` is hit.
+    pub fn run_with_limits(&mut self, limits: RunLimits) -> Result<StopReason, VmError> {
+        let deadline = limits.timeout.map(|t| Instant::now() + t);
+        let mut count = 0;
 
-            // Decode opcode, or fault with INSTRUCTION interrupt.
-            if let Some(o) = Operation::decode(opcode) {
-                operation = o;
-            } else {
-                self.trigger_instruction_interrupt();
-                continue;
+        loop {
+            if limits.max_instructions.is_some_and(|max| count >= max) {
+                return Ok(StopReason::InstructionLimit);
             }
 
-            debug!("Decoded {} operation", operation);
-
-            // Increment past opcode, then decode operands.
-            self.rp.wrapping_increment(1);
-            let (op1, op2) = self.decode_operands(operation);
-
-            // If we faulted with either INSTRUCTION or MEM, handle those.
-            if self.has_memory_interrupt() {
-                debug!("Memory interrupt while decoding operands.");
-                self.rp = rp;
-                self.trigger_memory_interrupt();
-                continue;
-            } else if self.has_instruction_interrupt() {
-                debug!("Instruction interrupt while decoding operands.");
-                self.rp = rp;
-                self.trigger_instruction_interrupt();
-                continue;
+            if count % TIMEOUT_CHECK_INTERVAL == 0
+                    && deadline.is_some_and(|d| Instant::now() >= d) {
+                return Ok(StopReason::Timeout);
             }
 
-            self.execute_operation(operation, op1, op2);
-
-            // Handle MEMORY, INSTRUCTION, and PROTECT interrupts first
-            // then schedule a fault if there is one and the EXTERNAL flag is set.
-            if self.has_memory_interrupt() {
-                self.rp = rp;
-                self.trigger_memory_interrupt();
-            } else if self.has_instruction_interrupt() {
-                self.rp = rp;
-                self.trigger_instruction_interrupt();
-            } else if self.has_protect_interrupt() {
-                self.rp = rp;
-                self.trigger_protect_interrupt();
-            } else if self.flag_get(EXTERNAL_FLAG)
-                    && self.has_scheduled_interrupt() {
-                debug!("Scheduling fault from queue.");
-                self.trigger_next_interrupt();
+            count += 1;
+
+            match self.step()? {
+                StepOutcome::Halted(status) => return Ok(StopReason::Halted(status)),
+                StepOutcome::Watchpoint(hit) => return Ok(StopReason::Watchpoint(hit)),
+                _ => {}
             }
         }
     }
+
+    /// Fetches, decodes and executes a single instruction, then delivers
+    /// any interrupt it raised (or the next scheduled one).
+    pub fn step(&mut self) -> Result<StepOutcome, VmError> {
+        if let Some(status) = self.halted {
+            return Err(VmError::Halted(status));
+        }
+
+        // Save the old rp, if we interrupt.
+        let rp = self.rp;
+        self.instruction_start = rp;
+        self.watch_hit = None;
+        let opcode = self.mem_fetch_short(rp);
+
+        // Handle MEMORY interrupt retrieving opcode.
+        if self.has_memory_interrupt() {
+            debug!("Memory interrupt while reading opcode.");
+            return self.trigger_memory_interrupt();
+        }
+
+        let operation;
+
+        // Decode opcode, or fault with INSTRUCTION interrupt.
+        if let Some(o) = Operation::decode(opcode) {
+            operation = o;
+        } else {
+            return self.trigger_instruction_interrupt();
+        }
+
+        debug!("Decoded {} operation", operation);
+
+        // Increment past opcode, then decode operands.
+        self.rp.wrapping_increment(1);
+        let (op1, op2) = self.decode_operands(operation);
+
+        // If we faulted with either INSTRUCTION or MEM, handle those.
+        if self.has_memory_interrupt() {
+            debug!("Memory interrupt while decoding operands.");
+            self.rp = rp;
+            return self.trigger_memory_interrupt();
+        } else if self.has_instruction_interrupt() {
+            debug!("Instruction interrupt while decoding operands.");
+            self.rp = rp;
+            return self.trigger_instruction_interrupt();
+        }
+
+        self.execute_operation(operation, op1, op2);
+
+        if !(self.has_memory_interrupt() || self.has_instruction_interrupt()
+                || self.has_protect_interrupt()) {
+            self.instructions_retired += 1;
+            self.ports.tick();
+        }
+
+        if let Some(status) = self.halted {
+            return Ok(StepOutcome::Halted(status));
+        }
+
+        if let Some(kind) = self.ports.take_reset() {
+            self.reset(kind);
+            return Ok(StepOutcome::Reset(kind));
+        }
+
+        // Handle MEMORY, INSTRUCTION, and PROTECT interrupts first
+        // then schedule a fault if there is one and the EXTERNAL flag is set.
+        if self.has_memory_interrupt() {
+            self.rp = rp;
+            self.trigger_memory_interrupt()
+        } else if self.has_instruction_interrupt() {
+            self.rp = rp;
+            self.trigger_instruction_interrupt()
+        } else if self.has_protect_interrupt() {
+            self.rp = rp;
+            self.trigger_protect_interrupt()
+        } else if let Some(hit) = self.watch_hit.take() {
+            // Any scheduled interrupt is delivered on the next step.
+            Ok(StepOutcome::Watchpoint(hit))
+        } else if self.flag_get(EXTERNAL_FLAG)
+                && self.has_scheduled_interrupt() {
+            debug!("Scheduling fault from queue.");
+            self.trigger_next_interrupt()
+        } else {
+            Ok(StepOutcome::Executed)
+        }
+    }
+}
+
+impl fmt::Display for Cpu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, r) in self.reg.iter().enumerate() {
+            write!(f, "r{:<2} = 0x{:08X}", i, r)?;
+            write!(f, "{}", if i % 4 == 3 { "\n" } else { "  " })?;
+        }
+
+        writeln!(f, "rp  = 0x{:08X}  rflags = 0x{:08X}", self.rp, self.rflags)?;
+        writeln!(f, "rm  = 0x{:08X}  ri  = 0x{:08X}  rf  = 0x{:08X}", self.rm, self.ri, self.rf)?;
+        write!(f, "rks = 0x{:08X}  rkt = 0x{:08X}", self.rks, self.rkt)
+    }
 }
@@ -21,10 +21,38 @@ impl<T, E> UnwrapOrDie<T> for Result<T, E> {
 pub const ERR_PARSE_MEM_SIZE: &str =
 "Cannot parse memory size argument. Check formatting!";
 
-pub const INVALID_FILE: &'static str =
-"Cannot open the given file. Please check that it exists and that \
-you have the right permissions to read it.";
+pub const ERR_PARSE_MAX_INSTRUCTIONS: &str =
+"Cannot parse instruction limit argument. Check formatting!";
 
-pub const CANNOT_READ_FILE: &'static str =
-"Cannot read the given file. Make sure that it is not corrupted and that \
-you have the right permissions to read it.";
+pub const ERR_PARSE_TIMEOUT: &str =
+"Cannot parse timeout argument. It should be a number of seconds.";
+
+pub const ERR_PARSE_TRIPLE_FAULT: &str =
+"Cannot parse triple fault action. It should be `reset` or `shutdown`.";
+
+pub const ERR_PARSE_ADDRESS: &str =
+"Cannot parse address argument. Use decimal or 0x-prefixed hexadecimal.";
+
+pub const ERR_PARSE_RFLAGS: &str =
+"Cannot parse flags argument. Use decimal or 0x-prefixed hexadecimal.";
+
+pub const ERR_PARSE_TLB_SIZE: &str =
+"Cannot parse TLB size argument. Check formatting!";
+
+pub const ERR_PARSE_REGION: &str =
+"Cannot parse memory region. Use BASE:SIZE for RAM and BASE:FILE for ROM.";
+
+pub const ERR_PARSE_ROM_WRITES: &str =
+"Cannot parse ROM write behaviour. It should be `fault` or `ignore`.";
+
+pub const ERR_PARSE_ALIGNMENT: &str =
+"Cannot parse alignment policy. It should be `allow`, `fault` or `warn`.";
+
+pub const ERR_PARSE_WATCHPOINT: &str =
+"Cannot parse watchpoint. Use ADDR[:LEN][:rwx], e.g. 0x400:4:rw.";
+
+pub const ERR_PARSE_CACHE: &str =
+"Cannot parse cache. Use SIZE:ASSOC:LINE[:POLICY], e.g. 8K:2:32:lru.";
+
+pub const ERR_PARSE_UNCLAIMED_PORTS: &str =
+"Cannot parse unclaimed port behaviour. It should be `open` or `fault`.";
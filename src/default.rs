@@ -1 +1,21 @@
 pub const DEFAULT_MEM_SIZE: u32 = 2048;
+pub const MIN_MEM_SIZE: u32 = 128;
+/// RAM regions bigger than this are allocated lazily.
+pub const DENSE_RAM_LIMIT: u64 = 64 * 1024 * 1024;
+
+pub const DEFAULT_RESET_VECTOR: u32 = 0;
+pub const DEFAULT_RFLAGS: u32 = 0;
+pub const DEFAULT_STACK_POINTER: u32 = 0;
+
+pub const DEFAULT_TLB_SIZE: usize = 16;
+
+pub const DEFAULT_PIC_VECTOR_BASE: u8 = 0x20;
+pub const DEFAULT_TIMER_LINE: u8 = 0;
+pub const DEFAULT_UART_LINE: u8 = 4;
+/// Lines which start out level-triggered. The UART holds its line high for
+/// as long as it wants attention, so it would stall on an edge-triggered one.
+pub const DEFAULT_PIC_TRIGGER: u8 = 1 << DEFAULT_UART_LINE;
+
+pub const DEFAULT_L1_SIZE: u32 = 8 * 1024;
+pub const DEFAULT_L1_ASSOCIATIVITY: u32 = 2;
+pub const DEFAULT_CACHE_LINE_SIZE: u32 = 32;
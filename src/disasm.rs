@@ -0,0 +1,126 @@
+use std::fmt;
+
+use cpu::Cpu;
+use mem::Mem;
+use operation::{Operation, Operand, OffsetType, Prototype};
+
+/// A decoded instruction, as found in memory.
+#[derive(Debug, Copy, Clone)]
+pub struct Instruction {
+    pub addr: u32,
+    pub operation: Operation,
+    pub op1: Operand,
+    pub op2: Operand,
+    /// Encoded length in bytes.
+    pub len: u32
+}
+
+/// Decodes instructions from a CPU's memory without executing them or
+/// disturbing its state. Mirrors the decoding done by `OperandParse`.
+pub struct Disassembler<'a> {
+    cpu: &'a Cpu,
+    /// Address of the next byte to decode.
+    pub addr: u32
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(cpu: &'a Cpu, addr: u32) -> Disassembler<'a> {
+        Disassembler { cpu, addr }
+    }
+
+    /// Decodes the instruction at `addr` and advances past it. Returns
+    /// `None` if it cannot be read or is not a valid instruction.
+    pub fn next_instruction(&mut self) -> Option<Instruction> {
+        let start = self.addr;
+        let operation = Operation::decode(self.next_byte()?)?;
+
+        let (op1, op2) = match Operation::prototype(operation) {
+            Prototype::N => (Operand::None, Operand::None),
+            Prototype::A | Prototype::X | Prototype::I => {
+                let l = self.next_operand()?;
+                (l, self.next_operand()?)
+            }
+            Prototype::P | Prototype::U => (self.next_operand()?, Operand::None),
+            Prototype::T => {
+                let imm = self.next_const(1)?;
+                (Operand::Constant(imm, OffsetType::AbsoluteNone), Operand::None)
+            }
+        };
+
+        Some(Instruction {
+            addr: start,
+            operation,
+            op1,
+            op2,
+            len: self.addr.wrapping_sub(start)
+        })
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let b = self.cpu.mem_peek_short(self.addr)?;
+        self.addr = self.addr.wrapping_add(1);
+        Some(b)
+    }
+
+    fn next_operand(&mut self) -> Option<Operand> {
+        let descriptor = self.next_byte()?;
+
+        Some(if descriptor & 0b1 == 0 {
+            if descriptor & 0b10 == 0 {
+                let const_sz = (descriptor >> 2) & 0b11;
+                let ty = match (descriptor >> 4) & 0b11 {
+                    0b00 => OffsetType::AbsoluteNone,
+                    0b01 => OffsetType::PositiveRelative,
+                    0b11 => OffsetType::NegativeRelative,
+                    _ => return None
+                };
+                Operand::Constant(self.next_const(const_sz)?, ty)
+            } else {
+                Operand::Register((descriptor >> 2) & 0b1111)
+            }
+        } else {
+            if descriptor & 0b10 == 0 {
+                let const_sz = (descriptor >> 2) & 0b11;
+                let register = (descriptor >> 4) & 0b1111;
+                Operand::IndirectConstant(register, self.next_const(const_sz)?)
+            } else {
+                let descriptor2 = self.next_byte()?;
+                let scale = (descriptor >> 2) & 0b11;
+                let base_register = (descriptor >> 4) & 0b1111;
+                let offset_register = descriptor2 & 0b1111;
+                let const_sz = (descriptor2 >> 4) & 0b11;
+                Operand::IndirectRegister(base_register, offset_register, scale,
+                                          self.next_const(const_sz)?)
+            }
+        })
+    }
+
+    fn next_const(&mut self, sz: u8) -> Option<u32> {
+        match sz {
+            0 => Some(0),
+            1 => self.next_byte().map(|b| b as u32),
+            2 => {
+                let lo = self.next_byte()? as u32;
+                let hi = self.next_byte()? as u32;
+                Some(lo | hi << 8)
+            }
+            _ => {
+                let val = self.cpu.mem_peek_long(self.addr)?;
+                self.addr = self.addr.wrapping_add(4);
+                Some(val)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:08X}: {}", self.addr, self.operation)?;
+
+        match (self.op1, self.op2) {
+            (Operand::None, _) => Ok(()),
+            (op1, Operand::None) => write!(f, " {}", op1),
+            (op1, op2) => write!(f, " {}, {}", op1, op2)
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use default::MIN_MEM_SIZE;
+
+/// Errors surfaced by the VM to whoever is embedding it.
+#[derive(Debug)]
+pub enum VmError {
+    /// The requested memory size is below `MIN_MEM_SIZE`.
+    MemoryTooSmall(u64),
+    /// An image of `size` bytes does not fit in memory when loaded at `addr`.
+    ImageTooLarge { addr: u32, size: usize },
+    /// A memory map region is empty, runs past the end of the address space
+    /// or overlaps another region.
+    InvalidRegion { base: u32, size: u64 },
+    /// A cache's size, associativity and line size are not powers of two
+    /// making up at least one set.
+    InvalidCache { size: u32, associativity: u32, line_size: u32 },
+    /// Some of the `count` I/O ports from `base` are already claimed.
+    PortsTaken { base: u32, count: u32 },
+    /// An image file could not be opened or read.
+    Io(io::Error),
+    /// Delivering this interrupt faulted, and so did delivering the
+    /// resulting DOUBLE_FAULT interrupt.
+    TripleFault(u8),
+    /// The CPU has executed HLT in kernel mode with this exit status and
+    /// cannot be stepped further.
+    Halted(u32)
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VmError::MemoryTooSmall(size) =>
+                write!(f, "There should be at least {} bytes of memory, not {}!", MIN_MEM_SIZE, size),
+            VmError::ImageTooLarge { addr, size } =>
+                write!(f, "Image of {} bytes does not fit in memory at 0x{:X}!", size, addr),
+            VmError::InvalidRegion { base, size } =>
+                write!(f, "Cannot map {} bytes at 0x{:X}: the region is empty, too large or overlaps another!",
+                       size, base),
+            VmError::InvalidCache { size, associativity, line_size } =>
+                write!(f, "Cannot make a {}-byte, {}-way cache with {}-byte lines!",
+                       size, associativity, line_size),
+            VmError::PortsTaken { base, count } =>
+                write!(f, "Cannot claim {} ports at 0x{:X}: some are already taken!", count, base),
+            VmError::Io(ref e) => write!(f, "Cannot read image: {}", e),
+            VmError::TripleFault(int) => write!(f, "Triple fault while handling 0x{:X}.", int),
+            VmError::Halted(status) => write!(f, "The CPU is halted with status {}.", status)
+        }
+    }
+}
+
+impl Error for VmError {}
+
+impl From<io::Error> for VmError {
+    fn from(e: io::Error) -> VmError {
+        VmError::Io(e)
+    }
+}
@@ -375,7 +375,7 @@ impl Execute for Cpu {
                 if self.flag_get(PROTECT_FLAG) {
                     self.interrupt_queue.push_back(HALT_INTERRUPT);
                 } else {
-                    fatal!("Halt instruction reached!");
+                    self.halted = Some(self.reg[0]);
                 }
             },
             INT => {
@@ -410,6 +410,7 @@ impl Execute for Cpu {
                 } else {
                     if let Some(val) = self.get_op_long(op1) {
                         self.rm = val;
+                        self.tlb.flush();
                     }
                 }
             },
@@ -519,7 +520,7 @@ impl Execute for Cpu {
             },
             POP => {
                 let rs = self.reg[15];
-                let val = self.mem_get_long(rs);
+                let val = self.mem_stack_get_long(rs);
 
                 if self.store_op_long(op1, val) {
                     self.reg[15].wrapping_increment(4);
@@ -527,7 +528,7 @@ impl Execute for Cpu {
             },
             POPS => {
                 let rs = self.reg[15];
-                let val = self.mem_get_short(rs);
+                let val = self.mem_stack_get_short(rs);
 
                 if self.store_op_short(op1, val) {
                     self.reg[15].wrapping_increment(1);
@@ -537,7 +538,7 @@ impl Execute for Cpu {
                 let rs = self.reg[15].wrapping_sub(4);
 
                 if let Some(val) = self.get_op_long(op1) {
-                    self.mem_set_long(rs, val);
+                    self.mem_stack_set_long(rs, val);
 
                     if !self.has_memory_interrupt() {
                         self.reg[15] = rs;
@@ -548,7 +549,7 @@ impl Execute for Cpu {
                 let rs = self.reg[15].wrapping_sub(1);
 
                 if let Some(val) = self.get_op_short(op1) {
-                    self.mem_set_short(rs, val);
+                    self.mem_stack_set_short(rs, val);
 
                     if !self.has_memory_interrupt() {
                         self.reg[15] = rs;
@@ -561,7 +562,7 @@ impl Execute for Cpu {
                 for i in 4..15 {
                     let rs = self.reg[15];
                     let reg = self.reg[i];
-                    self.mem_set_long(rs, reg);
+                    self.mem_stack_set_long(rs, reg);
 
                     if !self.has_memory_interrupt() {
                         self.reg[15].wrapping_increment(4);
@@ -577,7 +578,7 @@ impl Execute for Cpu {
                 for i in (4..15).rev() {
                     let rs = self.reg[15].wrapping_sub(4);
                     let reg = self.reg[i];
-                    self.mem_set_long(rs, reg);
+                    self.mem_stack_set_long(rs, reg);
 
                     if !self.has_memory_interrupt() {
                         self.reg[15] = rs;
@@ -587,31 +588,67 @@ impl Execute for Cpu {
                     }
                 }
             }
-            IN => {},
-            INS => {},
+            // Ports are only for the kernel. Reading one can have side
+            // effects, so check the destination first.
+            IN => {
+                if self.flag_get(PROTECT_FLAG) {
+                    self.protect_interrupt = true;
+                } else if let Some(port) = self.get_op_long(op1) {
+                    if self.probe_op_store(op2, 4) {
+                        match self.ports.read_long(port) {
+                            Some(val) => { self.store_op_long(op2, val); },
+                            None => self.protect_interrupt = true
+                        }
+                    }
+                }
+            },
+            INS => {
+                if self.flag_get(PROTECT_FLAG) {
+                    self.protect_interrupt = true;
+                } else if let Some(port) = self.get_op_long(op1) {
+                    if self.probe_op_store(op2, 1) {
+                        match self.ports.read_short(port) {
+                            Some(val) => { self.store_op_short(op2, val); },
+                            None => self.protect_interrupt = true
+                        }
+                    }
+                }
+            },
             OUT => {
-                if let Some((port, val)) = self.get_ops_long(op1, op2) {
-                    println!("0x{:X}: {} {}", port, val as u8 as char, val);
+                if self.flag_get(PROTECT_FLAG) {
+                    self.protect_interrupt = true;
+                } else if let Some((port, val)) = self.get_ops_long(op1, op2) {
+                    if !self.ports.write_long(port, val) {
+                        self.protect_interrupt = true;
+                    }
                 }
             },
             OUTS => {
-                if let Some((port, val)) = self.get_ops_short(op1, op2) {
-                    println!("0x{:X}: {} {}", port, val as char, val);
+                if self.flag_get(PROTECT_FLAG) {
+                    self.protect_interrupt = true;
+                } else if let Some(port) = self.get_op_long(op1) {
+                    if let Some(val) = self.get_op_short(op2) {
+                        if !self.ports.write_short(port, val) {
+                            self.protect_interrupt = true;
+                        }
+                    }
                 }
             },
+            // Make sure both stores will succeed before reading anything,
+            // so a faulting exchange has no side effects on devices.
             XCHG => {
-                if let Some((val1, val2)) = self.get_ops_long(op1, op2) {
-                    if !(self.store_op_long(op1, val2) && self.store_op_long(op2, val1)) {
-                        self.store_op_long(op1, val1);
-                        self.store_op_long(op2, val2);
+                if self.probe_op_store(op1, 4) && self.probe_op_store(op2, 4) {
+                    if let Some((val1, val2)) = self.get_ops_long(op1, op2) {
+                        self.store_op_long(op1, val2);
+                        self.store_op_long(op2, val1);
                     }
                 }
             },
             XCHGS => {
-                if let Some((val1, val2)) = self.get_ops_short(op1, op2) {
-                    if !(self.store_op_short(op1, val2) && self.store_op_short(op2, val1)) {
-                        self.store_op_short(op1, val1);
-                        self.store_op_short(op2, val2);
+                if self.probe_op_store(op1, 1) && self.probe_op_store(op2, 1) {
+                    if let Some((val1, val2)) = self.get_ops_short(op1, op2) {
+                        self.store_op_short(op1, val2);
+                        self.store_op_short(op2, val1);
                     }
                 }
             },
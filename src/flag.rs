@@ -6,9 +6,27 @@ pub const NEGATIVE_FLAG: u32 = 0b100;
 pub const OVERFLOW_FLAG: u32 = 0b1000;
 pub const PROTECT_FLAG: u32 = 0b10000;
 pub const EXTERNAL_FLAG: u32 = 0b100000;
+/// Translate addresses through the page tables at rm in kernel mode too,
+/// not just in user (PROTECT) mode.
+pub const KERNEL_PAGING_FLAG: u32 = 0b1000000;
+/// Raise ALIGNMENT on misaligned long accesses, whatever the CPU's
+/// alignment policy.
+pub const ALIGNMENT_CHECK_FLAG: u32 = 0b10000000;
 
 pub const ARITH_FLAGS_MASK: u32 = 0b1111;
 
+/// Every flag along with its name, for displaying rflags.
+pub const FLAG_NAMES: [(u32, &str); 8] = [
+    (CARRY_FLAG, "CARRY"),
+    (ZERO_FLAG, "ZERO"),
+    (NEGATIVE_FLAG, "NEGATIVE"),
+    (OVERFLOW_FLAG, "OVERFLOW"),
+    (PROTECT_FLAG, "PROTECT"),
+    (EXTERNAL_FLAG, "EXTERNAL"),
+    (KERNEL_PAGING_FLAG, "KERNEL_PAGING"),
+    (ALIGNMENT_CHECK_FLAG, "ALIGNMENT_CHECK")
+];
+
 const U32_MASK: u64 = 0xFFFF_FFFF;
 const LONG_SIGN_BIT: u32 = 0x8000_0000;
 const U8_MASK: u32 = 0xFF;
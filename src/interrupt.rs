@@ -1,4 +1,6 @@
-use cpu::Cpu;
+use cpu::{Cpu, StepOutcome, TripleFaultAction};
+use error::VmError;
+use reset::{Reset, ResetKind};
 use mem::Mem;
 use flag::*;
 
@@ -6,6 +8,8 @@ pub const MEMORY_INTERRUPT: u8 = 0;
 pub const PROTECT_INTERRUPT: u8 = 1;
 pub const INSTRUCTION_INTERRUPT: u8 = 2;
 pub const HALT_INTERRUPT: u8 = 3;
+pub const DOUBLE_FAULT_INTERRUPT: u8 = 4;
+pub const ALIGNMENT_INTERRUPT: u8 = 5;
 
 pub trait Interrupt {
     fn has_memory_interrupt(&self) -> bool;
@@ -13,12 +17,14 @@ pub trait Interrupt {
     fn has_instruction_interrupt(&self) -> bool;
     fn has_scheduled_interrupt(&self) -> bool;
 
-    fn trigger_memory_interrupt(&mut self);
-    fn trigger_protect_interrupt(&mut self);
-    fn trigger_instruction_interrupt(&mut self);
-    fn trigger_next_interrupt(&mut self);
+    fn trigger_memory_interrupt(&mut self) -> Result<StepOutcome, VmError>;
+    fn trigger_protect_interrupt(&mut self) -> Result<StepOutcome, VmError>;
+    fn trigger_instruction_interrupt(&mut self) -> Result<StepOutcome, VmError>;
+    fn trigger_next_interrupt(&mut self) -> Result<StepOutcome, VmError>;
 
-    fn trigger_interrupt(&mut self, int: u8);
+    fn trigger_interrupt(&mut self, int: u8) -> Result<StepOutcome, VmError>;
+    fn trigger_fault(&mut self, int: u8, code: Option<u32>) -> Result<StepOutcome, VmError>;
+    fn enter_interrupt(&mut self, int: u8, code: Option<u32>) -> bool;
 }
 
 impl Interrupt for Cpu {
@@ -35,35 +41,87 @@ impl Interrupt for Cpu {
     }
 
     fn has_scheduled_interrupt(&self) -> bool {
-        !self.interrupt_queue.is_empty()
+        !self.interrupt_queue.is_empty() || self.ports.pic.has_pending()
     }
 
-    fn trigger_memory_interrupt(&mut self) {
+    fn trigger_memory_interrupt(&mut self) -> Result<StepOutcome, VmError> {
         debug!("Trigger memory interrupt.");
         let mem_addr = self.mem_interrupt_address.unwrap();
+        let code = self.mem_fault_code.take();
         self.mem_interrupt_address = None;
 
-        self.rm = mem_addr;
-        self.trigger_interrupt(MEMORY_INTERRUPT);
+        // Misaligned accesses are reported through ALIGNMENT instead.
+        let int = if self.mem_misaligned {
+            self.mem_misaligned = false;
+            ALIGNMENT_INTERRUPT
+        } else {
+            MEMORY_INTERRUPT
+        };
+
+        self.rf = mem_addr;
+        self.trigger_fault(int, code)
     }
 
-    fn trigger_protect_interrupt(&mut self) {
+    fn trigger_protect_interrupt(&mut self) -> Result<StepOutcome, VmError> {
         self.protect_interrupt = false;
-        self.trigger_interrupt(PROTECT_INTERRUPT);
+        self.trigger_interrupt(PROTECT_INTERRUPT)
     }
 
-    fn trigger_instruction_interrupt(&mut self) {
+    fn trigger_instruction_interrupt(&mut self) -> Result<StepOutcome, VmError> {
         self.instr_interrupt = false;
-        self.trigger_interrupt(INSTRUCTION_INTERRUPT);
+        self.trigger_interrupt(INSTRUCTION_INTERRUPT)
     }
 
-    fn trigger_next_interrupt(&mut self) {
-        let interrupt = self.interrupt_queue.pop_front().unwrap();
+    fn trigger_next_interrupt(&mut self) -> Result<StepOutcome, VmError> {
+        let interrupt = match self.interrupt_queue.pop_front() {
+            Some(interrupt) => interrupt,
+            None => self.ports.pic.acknowledge().unwrap()
+        };
+
         debug!("Triggering interrupt {}!", interrupt);
-        self.trigger_interrupt(interrupt);
+        self.trigger_interrupt(interrupt)
+    }
+
+    fn trigger_interrupt(&mut self, int: u8) -> Result<StepOutcome, VmError> {
+        self.trigger_fault(int, None)
     }
 
-    fn trigger_interrupt(&mut self, int: u8) {
+    /// Like `trigger_interrupt`, but pushes `code` after the return address
+    /// if there is one. If entering the handler faults, the state is rolled
+    /// back and DOUBLE_FAULT is entered instead, with `int` as its code.
+    fn trigger_fault(&mut self, int: u8, code: Option<u32>) -> Result<StepOutcome, VmError> {
+        let (rflags, rs, rp) = (self.rflags, self.reg[15], self.rp);
+
+        if self.enter_interrupt(int, code) {
+            return Ok(StepOutcome::Interrupt(int));
+        }
+
+        warn!("Double fault while handling 0x{:X}.", int);
+        self.rflags = rflags;
+        self.reg[15] = rs;
+        self.rp = rp;
+
+        if self.enter_interrupt(DOUBLE_FAULT_INTERRUPT, Some(int as u32)) {
+            return Ok(StepOutcome::DoubleFault(int));
+        }
+
+        warn!("Triple fault while handling 0x{:X}.", int);
+        self.rflags = rflags;
+        self.reg[15] = rs;
+        self.rp = rp;
+
+        match self.triple_fault_action {
+            TripleFaultAction::Reset => {
+                self.reset(ResetKind::Warm);
+                Ok(StepOutcome::Reset(ResetKind::Warm))
+            }
+            TripleFaultAction::Shutdown => Err(VmError::TripleFault(int))
+        }
+    }
+
+    /// Saves the CPU state (and `code`) on the stack and jumps to the
+    /// handler for `int`. Returns false if any of that faulted.
+    fn enter_interrupt(&mut self, int: u8, code: Option<u32>) -> bool {
         let rflags = self.rflags;
         let rp = self.rp;
 
@@ -77,15 +135,23 @@ impl Interrupt for Cpu {
         self.push_stack(rflags);
         self.push_stack(rp);
 
+        if let Some(code) = code {
+            self.push_stack(code);
+        }
+
         self.flag_set(EXTERNAL_FLAG, false);
 
-        let off = self.ri + int as u32 * 4;
+        let off = self.ri.wrapping_add(int as u32 * 4);
         let jmp = self.mem_get_long(off);
 
         if self.has_memory_interrupt() {
-            fatal!("Double fault while handling 0x{:X}.", int);
+            self.mem_interrupt_address = None;
+            self.mem_fault_code = None;
+            self.mem_misaligned = false;
+            return false;
         }
 
         self.rp = jmp;
+        true
     }
 }
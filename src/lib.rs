@@ -0,0 +1,55 @@
+//! The Vesta Virtual Machine, a virtual CPU emulating the Janus instruction set.
+//!
+//! The `vesta` binary is a thin front-end over this library; other tools can
+//! embed the emulator by constructing a `Cpu` and driving it themselves.
+
+#[macro_use]
+extern crate log;
+
+pub mod default;
+pub mod error;
+pub mod cpu;
+pub mod operation;
+pub mod interrupt;
+pub mod flag;
+pub mod mem;
+pub mod bus;
+pub mod memmap;
+pub mod mmio;
+pub mod port;
+pub mod timer;
+pub mod pic;
+pub mod uart;
+pub mod mmu;
+pub mod tlb;
+pub mod watch;
+pub mod trace;
+pub mod cache;
+pub mod execute;
+pub mod reset;
+pub mod disasm;
+pub mod report;
+
+mod wrapping_util;
+
+pub use cpu::{Cpu, StepOutcome, StopReason, RunLimits, TripleFaultAction};
+pub use error::VmError;
+pub use operation::{Operation, Operand, OffsetType};
+pub use mem::{Mem, AlignmentPolicy};
+pub use bus::{Bus, Ram, SparseRam};
+pub use memmap::{MemoryMap, RegionKind, RomWrites};
+pub use mmio::MmioDevice;
+pub use port::{PortDevice, PortBus, UnclaimedPorts};
+pub use timer::Timer;
+pub use pic::Pic;
+pub use uart::Uart;
+pub use mmu::{Mmu, Translation};
+pub use tlb::Tlb;
+pub use watch::{Watchpoint, WatchHit};
+pub use trace::{TraceWriter, TraceReader, TraceRecord, TraceKind};
+pub use cache::{Cache, CacheConfig, CacheHierarchy, ReplacementPolicy};
+pub use interrupt::Interrupt;
+pub use flag::Flag;
+pub use reset::{Reset, ResetKind, PowerOnState};
+pub use disasm::{Disassembler, Instruction};
+pub use report::CrashReport;
@@ -1,5 +1,32 @@
 use wrapping_util::WrappingIncrement;
 use cpu::Cpu;
+use bus::{Bus, read_long_bytes};
+use mmu::{Mmu, Translation, FAULT_BUS};
+use flag::{Flag, ALIGNMENT_CHECK_FLAG};
+use interrupt::Interrupt;
+use watch::WatchHit;
+use trace::{TraceKind, TraceRecord};
+
+/// The kind of a memory access, for address translation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// Reading the instruction stream.
+    Fetch
+}
+
+/// What the CPU does when a long is read or written at an address which is
+/// not a multiple of 4. Instruction fetches are never checked.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AlignmentPolicy {
+    /// Perform the access as usual.
+    Allow,
+    /// Raise an ALIGNMENT interrupt instead of performing the access.
+    Fault,
+    /// Perform the access, but log a warning.
+    Warn
+}
 
 pub trait Mem {
     fn mem_get_short(&mut self, loc: u32) -> u8;
@@ -7,81 +34,350 @@ pub trait Mem {
     fn mem_set_short(&mut self, loc: u32, val: u8);
     fn mem_set_long(&mut self, loc: u32, val: u32);
 
+    /// Accesses the stack for PUSH and POP style instructions. These behave
+    /// just like `mem_get_*` and `mem_set_*`, but show up in traces as
+    /// pushes and pops.
+    fn mem_stack_get_short(&mut self, loc: u32) -> u8;
+    fn mem_stack_get_long(&mut self, loc: u32) -> u32;
+    fn mem_stack_set_short(&mut self, loc: u32, val: u8);
+    fn mem_stack_set_long(&mut self, loc: u32, val: u32);
+
+    /// Reads from the instruction stream.
+    fn mem_fetch_short(&mut self, loc: u32) -> u8;
+    fn mem_fetch_long(&mut self, loc: u32) -> u32;
+
+    /// Reads memory without raising interrupts or any other side effects,
+    /// for debuggers and the like.
+    fn mem_peek_short(&self, loc: u32) -> Option<u8>;
+    fn mem_peek_long(&self, loc: u32) -> Option<u32>;
+
+    /// Checks that a `len`-byte access at `loc` would succeed, raising the
+    /// MEMORY (or ALIGNMENT) interrupt it would otherwise, but without
+    /// touching memory or the page tables.
+    fn mem_probe(&mut self, loc: u32, len: u32, access: Access) -> bool;
+
+    /// Records a MEMORY interrupt for `loc`, unless one is already pending.
+    fn mem_fault(&mut self, loc: u32);
+
     fn push_stack(&mut self, word: u32);
     fn pop_stack(&mut self) -> u32;
 }
 
-impl Mem for Cpu {
-    fn mem_get_short(&mut self, loc: u32) -> u8 {
-        if loc as usize > self.mem.len() {
-            if !self.mem_interrupt_address.is_some() {
-                self.mem_interrupt_address = Some(loc);
-            }
+/// Are the physical addresses of a long's bytes consecutive?
+fn is_contiguous(pa: &[u32; 4]) -> bool {
+    pa[0].checked_add(3) == Some(pa[3])
+}
 
-            debug!("Memory access out of bounds @ 0x{:X}", loc);
-            0
-        } else {
-            debug!("Reading mem short at {}", loc);
-            self.mem[loc as usize]
+fn bus_read_long(bus: &mut dyn Bus, pa: &[u32; 4]) -> Option<u32> {
+    if is_contiguous(pa) {
+        return bus.read_long(pa[0]);
+    }
+
+    let mut val = 0;
+
+    for (i, &a) in pa.iter().enumerate() {
+        val |= (bus.read_short(a)? as u32) << (8 * i);
+    }
+
+    Some(val)
+}
+
+fn bus_write_long(bus: &mut dyn Bus, pa: &[u32; 4], val: u32) -> bool {
+    if is_contiguous(pa) {
+        return bus.write_long(pa[0], val);
+    }
+
+    pa.iter().enumerate().all(|(i, &a)| bus.write_short(a, (val >> (8 * i)) as u8))
+}
+
+/// Does a `width`-byte access at `loc` hit a watchpoint which has not
+/// already been hit this step?
+fn watched(cpu: &Cpu, loc: u32, width: u32, access: Access) -> bool {
+    cpu.watch_hit.is_none() && cpu.watchpoints.iter().any(|w| w.matches(loc, width, access))
+}
+
+fn record_watch_hit(cpu: &mut Cpu, loc: u32, width: u32, access: Access, old: Option<u32>, new: u32) {
+    debug!("Watchpoint hit @ 0x{:X}", loc);
+
+    cpu.watch_hit = Some(WatchHit {
+        rp: cpu.instruction_start,
+        addr: loc,
+        width,
+        access,
+        old,
+        new
+    });
+}
+
+/// How a trace records an access, which is a stack push or pop if `stack`.
+fn trace_kind(access: Access, stack: bool) -> TraceKind {
+    match (access, stack) {
+        (Access::Fetch, _) => TraceKind::Fetch,
+        (Access::Read, false) => TraceKind::Read,
+        (Access::Read, true) => TraceKind::Pop,
+        (Access::Write, false) => TraceKind::Write,
+        (Access::Write, true) => TraceKind::Push
+    }
+}
+
+/// Reports a successful access to anything observing memory traffic.
+fn record_access(cpu: &mut Cpu, va: u32, pa: u32, width: u8, kind: TraceKind) {
+    if let Some(ref mut caches) = cpu.caches {
+        caches.access(pa, width as u32, kind == TraceKind::Fetch);
+    }
+
+    let instruction = cpu.instructions_retired;
+
+    let result = match cpu.trace {
+        Some(ref mut trace) => trace.record(&TraceRecord { instruction, va, pa, width, kind }),
+        None => return
+    };
+
+    if let Err(e) = result {
+        warn!("Cannot write trace, so tracing has stopped: {}", e);
+        cpu.trace = None;
+    }
+}
+
+/// Reads a byte, which is a stack pop if `stack`.
+fn read_short(cpu: &mut Cpu, loc: u32, access: Access, stack: bool) -> u8 {
+    let translation = match cpu.translate(loc, access) {
+        Some(t) => t,
+        None => return 0
+    };
+
+    if let Some(val) = cpu.bus.read_short(translation.pa) {
+        debug!("Reading mem short at {}", loc);
+        cpu.mark_used(translation, access);
+        record_access(cpu, loc, translation.pa, 1, trace_kind(access, stack));
+
+        if watched(cpu, loc, 1, access) {
+            record_watch_hit(cpu, loc, 1, access, Some(val as u32), val as u32);
         }
+
+        val
+    } else {
+        debug!("Memory access out of bounds @ 0x{:X}", loc);
+        cpu.mem_fault(loc);
+        0
     }
+}
 
-    fn mem_get_long(&mut self, loc: u32) -> u32 {
-        if loc.wrapping_add(4) < loc ||
-           loc.wrapping_add(4) as usize > self.mem.len() {
-            if !self.mem_interrupt_address.is_some() {
-                self.mem_interrupt_address = Some(loc);
-            }
+/// Writes a byte, which is a stack push if `stack`.
+fn write_short(cpu: &mut Cpu, loc: u32, val: u8, stack: bool) {
+    let translation = match cpu.translate(loc, Access::Write) {
+        Some(t) => t,
+        None => return
+    };
 
-            debug!("Memory access out of bounds @ 0x{:X} (long)", loc);
-            0
-        } else {
-            debug!("Reading mem long at {}", loc);
-            (self.mem[loc.wrapping_add(0) as usize] as u32) << 0  |
-            (self.mem[loc.wrapping_add(1) as usize] as u32) << 8  |
-            (self.mem[loc.wrapping_add(2) as usize] as u32) << 16 |
-            (self.mem[loc.wrapping_add(3) as usize] as u32) << 24
+    let watch = watched(cpu, loc, 1, Access::Write);
+    let old = if watch { cpu.bus.peek_short(translation.pa) } else { None };
+
+    if cpu.bus.write_short(translation.pa, val) {
+        cpu.mark_used(translation, Access::Write);
+        record_access(cpu, loc, translation.pa, 1, trace_kind(Access::Write, stack));
+
+        if watch {
+            record_watch_hit(cpu, loc, 1, Access::Write, old.map(|o| o as u32), val as u32);
         }
+    } else {
+        debug!("Memory access out of bounds @ 0x{:X}", loc);
+        cpu.mem_fault(loc);
     }
+}
 
-    fn mem_set_short(&mut self, loc: u32, val: u8) {
-        if loc as usize > self.mem.len() {
-            if !self.mem_interrupt_address.is_some() {
-                self.mem_interrupt_address = Some(loc);
-            }
+/// Does the alignment policy forbid a long data access at `loc`?
+fn alignment_faults(cpu: &Cpu, loc: u32) -> bool {
+    !loc.is_multiple_of(4)
+        && (cpu.alignment_policy == AlignmentPolicy::Fault || cpu.flag_get(ALIGNMENT_CHECK_FLAG))
+}
 
-            debug!("Memory access out of bounds @ 0x{:X}", loc);
-        } else {
-            self.mem[loc as usize] = val;
+/// Records an ALIGNMENT interrupt for `loc`, unless a memory interrupt is
+/// already pending.
+fn alignment_fault(cpu: &mut Cpu, loc: u32) {
+    debug!("Misaligned long access @ 0x{:X}", loc);
+
+    if !cpu.has_memory_interrupt() {
+        cpu.mem_interrupt_address = Some(loc);
+        cpu.mem_misaligned = true;
+    }
+}
+
+/// Applies the alignment policy to a long data access at `loc`, returning
+/// false if the access must not go ahead.
+fn check_alignment(cpu: &mut Cpu, loc: u32) -> bool {
+    if alignment_faults(cpu, loc) {
+        alignment_fault(cpu, loc);
+        return false;
+    }
+
+    if !loc.is_multiple_of(4) && cpu.alignment_policy == AlignmentPolicy::Warn {
+        warn!("Misaligned long access @ 0x{:X}", loc);
+    }
+
+    true
+}
+
+/// Reads a long, which is a stack pop if `stack`.
+fn read_long(cpu: &mut Cpu, loc: u32, access: Access, stack: bool) -> u32 {
+    if access != Access::Fetch && !check_alignment(cpu, loc) {
+        return 0;
+    }
+
+    let (pa, first, second) = match cpu.translate_long(loc, access) {
+        Some(t) => t,
+        None => return 0
+    };
+
+    if let Some(val) = bus_read_long(&mut *cpu.bus, &pa) {
+        debug!("Reading mem long at {}", loc);
+        mark_long_used(cpu, first, second, access);
+        record_access(cpu, loc, pa[0], 4, trace_kind(access, stack));
+
+        if watched(cpu, loc, 4, access) {
+            record_watch_hit(cpu, loc, 4, access, Some(val), val);
+        }
+
+        val
+    } else {
+        debug!("Memory access out of bounds @ 0x{:X} (long)", loc);
+        cpu.mem_fault(loc);
+        0
+    }
+}
+
+/// Writes a long, which is a stack push if `stack`.
+fn write_long(cpu: &mut Cpu, loc: u32, val: u32, stack: bool) {
+    if !check_alignment(cpu, loc) {
+        return;
+    }
+
+    let (pa, first, second) = match cpu.translate_long(loc, Access::Write) {
+        Some(t) => t,
+        None => return
+    };
+
+    let watch = watched(cpu, loc, 4, Access::Write);
+    let old = if watch { cpu.mem_peek_long(loc) } else { None };
+
+    if bus_write_long(&mut *cpu.bus, &pa, val) {
+        mark_long_used(cpu, first, second, Access::Write);
+        record_access(cpu, loc, pa[0], 4, trace_kind(Access::Write, stack));
+
+        if watch {
+            record_watch_hit(cpu, loc, 4, Access::Write, old, val);
         }
+    } else {
+        debug!("Memory access out of bounds @ 0x{:X} (long)", loc);
+        cpu.mem_fault(loc);
+    }
+}
+
+fn mark_long_used(cpu: &mut Cpu, first: Translation, second: Option<Translation>, access: Access) {
+    cpu.mark_used(first, access);
+
+    if let Some(second) = second {
+        cpu.mark_used(second, access);
+    }
+}
+
+impl Mem for Cpu {
+    fn mem_get_short(&mut self, loc: u32) -> u8 {
+        read_short(self, loc, Access::Read, false)
+    }
+
+    fn mem_get_long(&mut self, loc: u32) -> u32 {
+        read_long(self, loc, Access::Read, false)
+    }
+
+    fn mem_set_short(&mut self, loc: u32, val: u8) {
+        write_short(self, loc, val, false)
     }
 
     fn mem_set_long(&mut self, loc: u32, val: u32) {
-        if loc.wrapping_add(4) < loc ||
-           loc.wrapping_add(4) as usize > self.mem.len() {
-            if !self.mem_interrupt_address.is_some() {
-                self.mem_interrupt_address = Some(loc);
+        write_long(self, loc, val, false)
+    }
+
+    fn mem_stack_get_short(&mut self, loc: u32) -> u8 {
+        read_short(self, loc, Access::Read, true)
+    }
+
+    fn mem_stack_get_long(&mut self, loc: u32) -> u32 {
+        read_long(self, loc, Access::Read, true)
+    }
+
+    fn mem_stack_set_short(&mut self, loc: u32, val: u8) {
+        write_short(self, loc, val, true)
+    }
+
+    fn mem_stack_set_long(&mut self, loc: u32, val: u32) {
+        write_long(self, loc, val, true)
+    }
+
+    fn mem_fetch_short(&mut self, loc: u32) -> u8 {
+        read_short(self, loc, Access::Fetch, false)
+    }
+
+    fn mem_fetch_long(&mut self, loc: u32) -> u32 {
+        read_long(self, loc, Access::Fetch, false)
+    }
+
+    fn mem_peek_short(&self, loc: u32) -> Option<u8> {
+        self.bus.peek_short(self.translate_peek(loc)?)
+    }
+
+    fn mem_peek_long(&self, loc: u32) -> Option<u32> {
+        read_long_bytes(loc, |a| self.mem_peek_short(a))
+    }
+
+    fn mem_probe(&mut self, loc: u32, len: u32, access: Access) -> bool {
+        // Warnings are left to the access itself.
+        if len == 4 && access != Access::Fetch && alignment_faults(self, loc) {
+            alignment_fault(self, loc);
+            return false;
+        }
+
+        let pa = if len == 4 {
+            match self.translate_long(loc, access) {
+                Some((pa, _, _)) => pa.to_vec(),
+                None => return false
+            }
+        } else {
+            match self.translate(loc, access) {
+                Some(translation) => vec![translation.pa],
+                None => return false
             }
+        };
+
+        let write = access == Access::Write;
 
-            debug!("Memory access out of bounds @ 0x{:X} (long)", loc);
+        if pa.iter().all(|&a| self.bus.accepts(a, write)) {
+            true
         } else {
-            self.mem[loc.wrapping_add(0) as usize] = ((val >> 0) & 0xFF) as u8;
-            self.mem[loc.wrapping_add(1) as usize] = ((val >> 8) & 0xFF) as u8;
-            self.mem[loc.wrapping_add(2) as usize] = ((val >> 16) & 0xFF) as u8;
-            self.mem[loc.wrapping_add(3) as usize] = ((val >> 24) & 0xFF) as u8;
+            debug!("Probe failed @ 0x{:X}", loc);
+            self.mem_fault(loc);
+            false
+        }
+    }
+
+    fn mem_fault(&mut self, loc: u32) {
+        if self.paging_enabled() {
+            self.page_fault(loc, FAULT_BUS);
+        } else if self.mem_interrupt_address.is_none() {
+            self.mem_interrupt_address = Some(loc);
         }
     }
 
     fn push_stack(&mut self, word: u32) {
         self.reg[15].wrapping_decrement(4);
         let rs = self.reg[15];
-        self.mem_set_long(rs, word);
+        write_long(self, rs, word, true);
         debug!("Pushing {} to {}", word, rs);
     }
 
     fn pop_stack(&mut self) -> u32 {
         let rs = self.reg[15];
-        let stk = self.mem_get_long(rs);
+        let stk = read_long(self, rs, Access::Read, true);
         self.reg[15].wrapping_increment(4);
 
         debug!("Popping {} from {}", stk, rs);
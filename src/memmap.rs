@@ -0,0 +1,301 @@
+//! A physical address space assembled from regions of RAM, ROM and
+//! memory-mapped devices. Addresses which fall in no region are unmapped,
+//! and any access to them raises a MEMORY interrupt.
+
+use std::fmt;
+
+use bus::{Bus, Ram, new_ram, read_long_bytes};
+use mmio::MmioDevice;
+use error::VmError;
+
+/// What happens when the guest writes to ROM.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RomWrites {
+    /// The write raises a MEMORY interrupt.
+    Fault,
+    /// The write is silently dropped.
+    Ignore
+}
+
+/// What backs a region of the memory map.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RegionKind {
+    Ram,
+    Rom(RomWrites),
+    /// A window onto a device, which sees addresses relative to the start
+    /// of the region.
+    Mmio
+}
+
+struct Region {
+    base: u32,
+    size: u64,
+    kind: RegionKind,
+    bus: Box<dyn Bus>
+}
+
+impl Region {
+    /// The last address in the region.
+    fn end(&self) -> u32 {
+        (self.base as u64 + self.size - 1) as u32
+    }
+}
+
+pub struct MemoryMap {
+    /// Non-overlapping regions, sorted by base address.
+    regions: Vec<Region>
+}
+
+impl MemoryMap {
+    /// Creates an empty map, with nothing mapped anywhere.
+    pub fn new() -> MemoryMap {
+        MemoryMap { regions: Vec::new() }
+    }
+
+    /// Maps `size` bytes of zeroed RAM at `base`, sparse if it is bigger
+    /// than `DENSE_RAM_LIMIT` (see `bus::new_ram`).
+    pub fn add_ram(&mut self, base: u32, size: u64) -> Result<(), VmError> {
+        self.add_region(base, size, RegionKind::Ram, new_ram(size))
+    }
+
+    /// Maps ROM holding `contents` at `base`.
+    pub fn add_rom(&mut self, base: u32, contents: &[u8], writes: RomWrites) -> Result<(), VmError> {
+        let size = contents.len() as u64;
+        let mut rom = Ram::new(contents.len() as u32);
+        rom.load(0, contents);
+
+        self.add_region(base, size, RegionKind::Rom(writes), Box::new(rom))
+    }
+
+    /// Maps `device` into the `size` bytes at `base`.
+    pub fn add_mmio(&mut self, base: u32, size: u32, device: Box<dyn MmioDevice>)
+            -> Result<(), VmError> {
+        self.add_region(base, size as u64, RegionKind::Mmio, Box::new(MmioWindow { size, device }))
+    }
+
+    fn add_region(&mut self, base: u32, size: u64, kind: RegionKind, bus: Box<dyn Bus>)
+            -> Result<(), VmError> {
+        let invalid = size == 0
+            || base as u64 + size > 1 << 32
+            || self.regions.iter().any(|r| base <= r.end() && (r.base as u64) < base as u64 + size);
+
+        if invalid {
+            return Err(VmError::InvalidRegion { base, size });
+        }
+
+        let index = self.regions.iter().position(|r| r.base > base).unwrap_or(self.regions.len());
+        self.regions.insert(index, Region { base, size, kind, bus });
+
+        Ok(())
+    }
+
+    /// Finds the index of the region holding `addr`, and the offset of `addr`
+    /// within it.
+    fn find(&self, addr: u32) -> Option<(usize, u32)> {
+        self.regions.iter()
+                    .position(|r| r.base <= addr && addr <= r.end())
+                    .map(|i| (i, addr - self.regions[i].base))
+    }
+
+    /// Finds the region holding all of `addr..addr + len`.
+    fn find_range(&self, addr: u32, len: u32) -> Option<(usize, u32)> {
+        let (i, offset) = self.find(addr)?;
+
+        if len as u64 <= self.regions[i].size - offset as u64 {
+            Some((i, offset))
+        } else {
+            None
+        }
+    }
+
+}
+
+/// Adapts a device to the `Bus` interface, so a region can hold it.
+struct MmioWindow {
+    size: u32,
+    device: Box<dyn MmioDevice>
+}
+
+impl Bus for MmioWindow {
+    fn size(&self) -> u64 {
+        self.size as u64
+    }
+
+    fn read_short(&mut self, addr: u32) -> Option<u8> {
+        self.device.read_short(addr)
+    }
+
+    fn write_short(&mut self, addr: u32, val: u8) -> bool {
+        self.device.write_short(addr, val)
+    }
+
+    fn read_long(&mut self, addr: u32) -> Option<u32> {
+        self.device.read_long(addr)
+    }
+
+    fn write_long(&mut self, addr: u32, val: u32) -> bool {
+        self.device.write_long(addr, val)
+    }
+
+    fn peek_short(&self, addr: u32) -> Option<u8> {
+        self.device.peek_short(addr)
+    }
+
+    fn accepts(&self, addr: u32, write: bool) -> bool {
+        self.device.accepts(addr, write)
+    }
+
+    fn load(&mut self, _addr: u32, _data: &[u8]) -> bool {
+        false
+    }
+
+    fn clear(&mut self) {}
+}
+
+impl Default for MemoryMap {
+    fn default() -> MemoryMap {
+        MemoryMap::new()
+    }
+}
+
+impl Bus for MemoryMap {
+    fn size(&self) -> u64 {
+        self.regions.iter().map(|r| r.size).sum()
+    }
+
+    fn read_short(&mut self, addr: u32) -> Option<u8> {
+        let (i, offset) = self.find(addr)?;
+        self.regions[i].bus.read_short(offset)
+    }
+
+    fn write_short(&mut self, addr: u32, val: u8) -> bool {
+        let (i, offset) = match self.find(addr) {
+            Some(found) => found,
+            None => return false
+        };
+
+        match self.regions[i].kind {
+            RegionKind::Rom(RomWrites::Fault) => false,
+            RegionKind::Rom(RomWrites::Ignore) => true,
+            _ => self.regions[i].bus.write_short(offset, val)
+        }
+    }
+
+    fn read_long(&mut self, addr: u32) -> Option<u32> {
+        if let Some((i, offset)) = self.find_range(addr, 4) {
+            return self.regions[i].bus.read_long(offset);
+        }
+
+        // The long spans regions, so read it a byte at a time.
+        read_long_bytes(addr, |a| self.read_short(a))
+    }
+
+    fn write_long(&mut self, addr: u32, val: u32) -> bool {
+        if let Some((i, offset)) = self.find_range(addr, 4) {
+            return match self.regions[i].kind {
+                RegionKind::Rom(RomWrites::Fault) => false,
+                RegionKind::Rom(RomWrites::Ignore) => true,
+                _ => self.regions[i].bus.write_long(offset, val)
+            };
+        }
+
+        // Make sure no byte of a long spanning regions faults before
+        // writing any of them.
+        let addrs = match addr.checked_add(3) {
+            Some(_) => [addr, addr + 1, addr + 2, addr + 3],
+            None => return false
+        };
+
+        if !addrs.iter().all(|&a| self.accepts(a, true)) {
+            return false;
+        }
+
+        addrs.iter().enumerate().all(|(i, &a)| self.write_short(a, (val >> (8 * i)) as u8))
+    }
+
+    fn peek_short(&self, addr: u32) -> Option<u8> {
+        let (i, offset) = self.find(addr)?;
+        self.regions[i].bus.peek_short(offset)
+    }
+
+    fn peek_long(&self, addr: u32) -> Option<u32> {
+        if let Some((i, offset)) = self.find_range(addr, 4) {
+            return self.regions[i].bus.peek_long(offset);
+        }
+
+        read_long_bytes(addr, |a| self.peek_short(a))
+    }
+
+    fn accepts(&self, addr: u32, write: bool) -> bool {
+        let (i, offset) = match self.find(addr) {
+            Some(found) => found,
+            None => return false
+        };
+
+        // Dropped writes to ROM still succeed.
+        match self.regions[i].kind {
+            RegionKind::Rom(RomWrites::Fault) if write => false,
+            _ => self.regions[i].bus.accepts(offset, write)
+        }
+    }
+
+    fn load(&mut self, addr: u32, data: &[u8]) -> bool {
+        if data.is_empty() {
+            return true;
+        }
+
+        // Images may only go in RAM or ROM, but may span several regions.
+        let fits = data.len() as u64 <= u32::MAX as u64
+            && addr.checked_add(data.len() as u32 - 1).is_some()
+            && (0..data.len() as u32).all(|i| match self.find(addr + i) {
+                Some((r, _)) => self.regions[r].kind != RegionKind::Mmio,
+                None => false
+            });
+
+        if !fits {
+            return false;
+        }
+
+        let mut loaded = 0;
+
+        while loaded < data.len() {
+            let (i, offset) = self.find(addr + loaded as u32).unwrap();
+            let region = &mut self.regions[i];
+            let len = ::std::cmp::min((data.len() - loaded) as u64, region.size - offset as u64) as usize;
+
+            region.bus.load(offset, &data[loaded..loaded + len]);
+            loaded += len;
+        }
+
+        true
+    }
+
+    fn clear(&mut self) {
+        for r in self.regions.iter_mut().filter(|r| r.kind == RegionKind::Ram) {
+            r.bus.clear();
+        }
+    }
+}
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegionKind::Ram => write!(f, "RAM"),
+            RegionKind::Rom(RomWrites::Fault) => write!(f, "ROM"),
+            RegionKind::Rom(RomWrites::Ignore) => write!(f, "ROM (writes ignored)"),
+            RegionKind::Mmio => write!(f, "MMIO")
+        }
+    }
+}
+
+impl fmt::Display for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Memory map:")?;
+
+        for r in &self.regions {
+            write!(f, "\n  0x{:08X}-0x{:08X}  {:>10} bytes  {}", r.base, r.end(), r.size, r.kind)?;
+        }
+
+        Ok(())
+    }
+}
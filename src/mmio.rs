@@ -0,0 +1,40 @@
+use bus::read_long_bytes;
+
+/// A device which answers loads and stores in a window of the physical
+/// address space. Offsets are relative to the start of the window it is
+/// mapped at with `MemoryMap::add_mmio`.
+///
+/// Reads may have side effects (popping a FIFO, acknowledging an interrupt).
+/// Only XCHG checks that its stores will succeed before reading anything;
+/// other instructions read their operands first, so one whose store then
+/// faults has still read the device, and the value is lost.
+pub trait MmioDevice {
+    fn read_short(&mut self, offset: u32) -> Option<u8>;
+    fn write_short(&mut self, offset: u32, val: u8) -> bool;
+
+    /// Reads a little-endian long. The default reads it a byte at a time.
+    fn read_long(&mut self, offset: u32) -> Option<u32> {
+        read_long_bytes(offset, |a| self.read_short(a))
+    }
+
+    /// Writes a little-endian long. The default writes it a byte at a time.
+    fn write_long(&mut self, offset: u32, val: u32) -> bool {
+        (0..4).all(|i| match offset.checked_add(i) {
+            Some(o) => self.write_short(o, (val >> (8 * i)) as u8),
+            None => false
+        })
+    }
+
+    /// Reads a byte without side effects, for debuggers. Devices whose
+    /// registers cannot be read that way return `None`, which is the
+    /// default.
+    fn peek_short(&self, _offset: u32) -> Option<u8> {
+        None
+    }
+
+    /// Would an access at `offset` succeed? This must not have side effects.
+    /// The default accepts everything in the window.
+    fn accepts(&self, _offset: u32, _write: bool) -> bool {
+        true
+    }
+}
@@ -0,0 +1,257 @@
+//! Two-level paging, rooted at the memory descriptor table register `rm`.
+//!
+//! When paging is enabled (see `Mmu::paging_enabled`), a virtual address is
+//! split into a 10-bit directory index, a 10-bit table index and a 12-bit
+//! page offset. `rm` holds the physical address of a page directory of 1024
+//! long entries; each present entry points to a page table of 1024 long
+//! entries, each of which maps one 4 KiB page. In both kinds of entry the
+//! top 20 bits hold a physical frame address and the low bits are flags.
+//!
+//! Permissions are the intersection of the directory and table entries'
+//! READ, WRITE, EXECUTE and USER bits. User-mode (PROTECT) accesses need
+//! USER, reads need READ, writes need WRITE and instruction fetches need
+//! EXECUTE.
+//!
+//! Every successful access through a translation sets ACCESSED in both
+//! entries, and every write also sets DIRTY in the table entry. These are
+//! only updated once the whole access has succeeded, so a faulting access
+//! leaves the page tables untouched.
+//!
+//! Successful walks are cached in the CPU's TLB, which is flushed whenever
+//! `rm` is loaded with LOM. Until then, edits to the page tables may not be
+//! seen.
+//!
+//! Any failed translation raises a MEMORY interrupt with the faulting
+//! virtual address in `rf`. While paging is enabled, every MEMORY interrupt
+//! also pushes one of the `FAULT_*` error codes after the return address,
+//! saying which check failed; the handler must pop it before IRET.
+
+use cpu::Cpu;
+use mem::{Mem, Access};
+use flag::*;
+use interrupt::Interrupt;
+use tlb::TlbEntry;
+
+pub const PAGE_SIZE: u32 = 4096;
+pub const PAGE_SHIFT: u32 = 12;
+pub const PAGE_OFFSET_MASK: u32 = PAGE_SIZE - 1;
+pub const ENTRIES_PER_TABLE: u32 = 1024;
+
+/// Physical frame address stored in a directory or table entry.
+pub const PTE_FRAME_MASK: u32 = !PAGE_OFFSET_MASK;
+/// The entry maps something; if clear, any access through it faults.
+pub const PTE_PRESENT: u32 = 0b1;
+pub const PTE_READ: u32 = 0b10;
+pub const PTE_WRITE: u32 = 0b100;
+pub const PTE_EXECUTE: u32 = 0b1000;
+/// User-mode code may access the page.
+pub const PTE_USER: u32 = 0b10000;
+/// Set by the MMU when the entry is used to translate an access.
+pub const PTE_ACCESSED: u32 = 0b100000;
+/// Set by the MMU in a table entry when its page is written to.
+pub const PTE_DIRTY: u32 = 0b1000000;
+/// Permission bits which are combined across both levels of the walk.
+pub const PTE_PERMISSION_MASK: u32 = PTE_READ | PTE_WRITE | PTE_EXECUTE | PTE_USER;
+
+/// Error codes pushed by a MEMORY interrupt while paging is enabled.
+pub const FAULT_NOT_PRESENT: u32 = 1;
+pub const FAULT_NO_READ: u32 = 2;
+pub const FAULT_NO_WRITE: u32 = 3;
+pub const FAULT_NO_EXECUTE: u32 = 4;
+/// A user-mode access to a page without PTE_USER.
+pub const FAULT_SUPERVISOR: u32 = 5;
+/// The translated physical address is not backed by anything.
+pub const FAULT_BUS: u32 = 6;
+
+/// Where a virtual address ended up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Translation {
+    /// Physical address.
+    pub pa: u32,
+    /// Physical addresses of the directory and table entries which mapped
+    /// it, or `None` if paging is off.
+    pub entries: Option<(u32, u32)>
+}
+
+pub trait Mmu {
+    /// Is address translation in effect for the current CPU mode?
+    fn paging_enabled(&self) -> bool;
+
+    /// Translates a virtual address to a physical one. If translation
+    /// fails, a MEMORY interrupt is recorded and `None` is returned.
+    fn translate(&mut self, va: u32, access: Access) -> Option<Translation>;
+    /// Translates each byte of the long at `va`, which may straddle two
+    /// pages, also returning the translation of each page involved.
+    /// Nothing is accessed unless every byte translates.
+    fn translate_long(&mut self, va: u32, access: Access)
+        -> Option<([u32; 4], Translation, Option<Translation>)>;
+    /// Sets the ACCESSED (and for writes, DIRTY) bits of the entries used by
+    /// a translation, once the access through it has succeeded.
+    fn mark_used(&mut self, translation: Translation, access: Access);
+
+    /// Translates without raising interrupts or any other side effects,
+    /// and without checking permissions.
+    fn translate_peek(&self, va: u32) -> Option<u32>;
+    /// Walks the page tables for `va`, returning its translation and
+    /// effective permissions, or the error code of the fault.
+    fn page_walk(&self, va: u32) -> Result<(Translation, u32), u32>;
+    /// Checks `access` against the effective permissions of a page.
+    fn check_access(&self, permissions: u32, access: Access) -> Result<(), u32>;
+    /// Records a MEMORY interrupt for `va` with an error code, unless one is
+    /// already pending.
+    fn page_fault(&mut self, va: u32, code: u32);
+}
+
+impl Mmu for Cpu {
+    fn paging_enabled(&self) -> bool {
+        self.flag_get(PROTECT_FLAG) || self.flag_get(KERNEL_PAGING_FLAG)
+    }
+
+    fn translate(&mut self, va: u32, access: Access) -> Option<Translation> {
+        if !self.paging_enabled() {
+            return Some(Translation { pa: va, entries: None });
+        }
+
+        let vpn = va >> PAGE_SHIFT;
+
+        let walk = match self.tlb.lookup(vpn) {
+            Some(entry) => Ok((Translation {
+                pa: entry.frame | (va & PAGE_OFFSET_MASK),
+                entries: Some(entry.entries)
+            }, entry.permissions)),
+            None => self.page_walk(va).map(|(translation, permissions)| {
+                self.tlb.insert(TlbEntry {
+                    vpn,
+                    frame: translation.pa & PTE_FRAME_MASK,
+                    permissions,
+                    entries: translation.entries.unwrap()
+                });
+
+                (translation, permissions)
+            })
+        };
+
+        let result = walk.and_then(|(translation, permissions)| {
+            self.check_access(permissions, access).map(|_| translation)
+        });
+
+        match result {
+            Ok(translation) => Some(translation),
+            Err(code) => {
+                debug!("Page fault @ 0x{:X}, code {}", va, code);
+                self.page_fault(va, code);
+                None
+            }
+        }
+    }
+
+    fn translate_long(&mut self, va: u32, access: Access)
+            -> Option<([u32; 4], Translation, Option<Translation>)> {
+        let last = match va.checked_add(3) {
+            Some(last) => last,
+            None => {
+                self.mem_fault(va);
+                return None;
+            }
+        };
+
+        let first = self.translate(va, access)?;
+        let first_pa = first.pa;
+
+        if last >> PAGE_SHIFT == va >> PAGE_SHIFT {
+            return Some(([first_pa, first_pa + 1, first_pa + 2, first_pa + 3], first, None));
+        }
+
+        // The long straddles two pages, so translate the start of the second.
+        let second_va = last & !PAGE_OFFSET_MASK;
+        let second = self.translate(second_va, access)?;
+        let mut pa = [0; 4];
+
+        for i in 0..4 {
+            let v = va + i;
+            pa[i as usize] = if v < second_va {
+                first_pa + i
+            } else {
+                second.pa + (v - second_va)
+            };
+        }
+
+        Some((pa, first, Some(second)))
+    }
+
+    fn mark_used(&mut self, translation: Translation, access: Access) {
+        let (pde_addr, pte_addr) = match translation.entries {
+            Some(entries) => entries,
+            None => return
+        };
+
+        let dirty = if access == Access::Write { PTE_DIRTY } else { 0 };
+
+        for &(addr, bits) in [(pde_addr, PTE_ACCESSED), (pte_addr, PTE_ACCESSED | dirty)].iter() {
+            if let Some(entry) = self.bus.peek_long(addr) {
+                if entry & bits != bits {
+                    self.bus.write_long(addr, entry | bits);
+                }
+            }
+        }
+    }
+
+    fn translate_peek(&self, va: u32) -> Option<u32> {
+        if self.paging_enabled() {
+            self.page_walk(va).ok().map(|(translation, _)| translation.pa)
+        } else {
+            Some(va)
+        }
+    }
+
+    fn page_walk(&self, va: u32) -> Result<(Translation, u32), u32> {
+        let dir_index = va >> (PAGE_SHIFT + 10);
+        let table_index = (va >> PAGE_SHIFT) & (ENTRIES_PER_TABLE - 1);
+
+        let pde_addr = (self.rm & PTE_FRAME_MASK).wrapping_add(dir_index * 4);
+        let pde = self.bus.peek_long(pde_addr).unwrap_or(0);
+
+        if pde & PTE_PRESENT == 0 {
+            return Err(FAULT_NOT_PRESENT);
+        }
+
+        let pte_addr = (pde & PTE_FRAME_MASK).wrapping_add(table_index * 4);
+        let pte = self.bus.peek_long(pte_addr).unwrap_or(0);
+
+        if pte & PTE_PRESENT == 0 {
+            return Err(FAULT_NOT_PRESENT);
+        }
+
+        let translation = Translation {
+            pa: (pte & PTE_FRAME_MASK) | (va & PAGE_OFFSET_MASK),
+            entries: Some((pde_addr, pte_addr))
+        };
+
+        Ok((translation, pde & pte & PTE_PERMISSION_MASK))
+    }
+
+    fn check_access(&self, permissions: u32, access: Access) -> Result<(), u32> {
+        if self.flag_get(PROTECT_FLAG) && permissions & PTE_USER == 0 {
+            return Err(FAULT_SUPERVISOR);
+        }
+
+        let (needed, code) = match access {
+            Access::Read => (PTE_READ, FAULT_NO_READ),
+            Access::Write => (PTE_WRITE, FAULT_NO_WRITE),
+            Access::Fetch => (PTE_EXECUTE, FAULT_NO_EXECUTE)
+        };
+
+        if permissions & needed == 0 {
+            Err(code)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn page_fault(&mut self, va: u32, code: u32) {
+        if !self.has_memory_interrupt() {
+            self.mem_interrupt_address = Some(va);
+            self.mem_fault_code = Some(code);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::fmt;
 
 use wrapping_util::WrappingIncrement;
 use cpu::Cpu;
-use mem::Mem;
+use mem::{Mem, Access};
 use interrupt::Interrupt;
 
 #[derive(Debug, Copy, Clone)]
@@ -326,7 +326,7 @@ impl OperandParse for Cpu {
 
     fn read_operand(&mut self) -> Operand {
         let rp = self.rp;
-        let descriptor = self.mem_get_short(rp);
+        let descriptor = self.mem_fetch_short(rp);
         self.rp.wrapping_increment(1);
 
         debug!("Reading operand descriptor: {} as {:b}", descriptor, descriptor);
@@ -354,7 +354,7 @@ impl OperandParse for Cpu {
             } else {
                 // REGISTER BASE
                 let rp = self.rp;
-                let descriptor2 = self.mem_get_short(rp);
+                let descriptor2 = self.mem_fetch_short(rp);
                 self.rp.wrapping_increment(1);
 
                 let scale = (descriptor >> 2) & 0b11;
@@ -374,18 +374,18 @@ impl OperandParse for Cpu {
         match sz {
             0 => 0, // 0-byte constant
             1 => { // 1-byte constant
-                let constant = self.mem_get_short(rp);
+                let constant = self.mem_fetch_short(rp);
                 self.rp.wrapping_increment(1);
                 constant as u32
             }
             2 => { // 2-byte constant
-                let constant = self.mem_get_short(rp) as u32 |
-                               self.mem_get_short(rp.wrapping_add(1)) as u32 >> 8;
+                let constant = self.mem_fetch_short(rp) as u32 |
+                               (self.mem_fetch_short(rp.wrapping_add(1)) as u32) << 8;
                 self.rp.wrapping_increment(2);
                 constant
             }
             3 => { // 4-byte constant
-                let constant = self.mem_get_long(rp);
+                let constant = self.mem_fetch_long(rp);
                 self.rp.wrapping_increment(4);
                 constant
             }
@@ -413,6 +413,9 @@ pub trait OperandCompute {
     fn get_op_short(&mut self, op: Operand) -> Option<u8>;
     fn get_ops_short(&mut self, op1: Operand, op2: Operand) -> Option<(u8, u8)>;
     fn store_op_short(&mut self, op: Operand, val: u8) -> bool;
+    /// Checks that a `len`-byte store to `op` would succeed, without
+    /// performing it.
+    fn probe_op_store(&mut self, op: Operand, len: u32) -> bool;
 }
 
 impl OperandCompute for Cpu {
@@ -541,6 +544,23 @@ impl OperandCompute for Cpu {
 
         !self.has_memory_interrupt()
     }
+
+    fn probe_op_store(&mut self, op: Operand, len: u32) -> bool {
+        match op {
+            Operand::None => unreachable!(),
+            Operand::Constant(_, _) => unreachable!(),
+            Operand::Register(_) => true,
+            Operand::IndirectConstant(r, c) => {
+                let addr = self.reg[r as usize].wrapping_add(c);
+                self.mem_probe(addr, len, Access::Write)
+            }
+            Operand::IndirectRegister(b, o, s, c) => {
+                let addr = (self.reg[o as usize] << s).wrapping_add(self.reg[b as usize])
+                                                      .wrapping_add(c);
+                self.mem_probe(addr, len, Access::Write)
+            }
+        }
+    }
 }
 
 impl fmt::Display for Operation {
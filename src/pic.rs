@@ -0,0 +1,163 @@
+//! A programmable interrupt controller, multiplexing the interrupt requests
+//! of port devices onto the CPU.
+//!
+//! Devices assert IRQ lines while they tick. A line asserted by any device
+//! is high, so several devices can share one. Edge-triggered lines request
+//! an interrupt when they go from low to high; level-triggered lines
+//! request one for as long as they are high.
+//!
+//! While EXTERNAL is set, the CPU takes the unmasked request with the best
+//! (lowest) priority, as long as it beats every line already in service,
+//! and jumps to vector `vector base + line`. The line then stays in service
+//! until the handler writes `PIC_EOI` to `PIC_COMMAND`.
+//!
+//! It is built into the port bus at `PIC_PORT`, with these registers:
+//!
+//! * `PIC_COMMAND`: write `PIC_EOI` to end the in-service interrupt with the
+//!   best priority. Reads give the in-service lines.
+//! * `PIC_MASK`: lines whose bits are set never interrupt.
+//! * `PIC_VECTOR_BASE`: vector of line 0.
+//! * `PIC_TRIGGER`: lines whose bits are set are level-triggered.
+//! * `PIC_REQUESTED`: read-only set of lines requesting an interrupt.
+//! * `PIC_PRIORITY + n`: priority of line `n`. Ties go to the lower line.
+
+use default::{DEFAULT_PIC_VECTOR_BASE, DEFAULT_PIC_TRIGGER};
+
+pub const PIC_PORT: u32 = 0x20;
+pub const PIC_LINES: u8 = 8;
+pub const PIC_PORTS: u32 = PIC_PRIORITY + PIC_LINES as u32;
+
+pub const PIC_COMMAND: u32 = 0;
+pub const PIC_MASK: u32 = 1;
+pub const PIC_VECTOR_BASE: u32 = 2;
+pub const PIC_TRIGGER: u32 = 3;
+pub const PIC_REQUESTED: u32 = 4;
+pub const PIC_PRIORITY: u32 = 5;
+
+/// End of interrupt command.
+pub const PIC_EOI: u8 = 0x20;
+
+pub struct Pic {
+    pub mask: u8,
+    pub vector_base: u8,
+    /// Level-triggered lines.
+    pub trigger: u8,
+    pub priorities: [u8; PIC_LINES as usize],
+    /// Lines requesting an interrupt.
+    pub requested: u8,
+    /// Lines whose interrupt is being handled.
+    pub in_service: u8,
+    /// Lines asserted during the current tick.
+    levels: u8,
+    /// Lines asserted during the previous tick.
+    previous: u8
+}
+
+impl Pic {
+    /// Creates a controller with every line unmasked, prioritised by line
+    /// number, and edge-triggered apart from those in `DEFAULT_PIC_TRIGGER`.
+    pub fn new() -> Pic {
+        let mut priorities = [0; PIC_LINES as usize];
+
+        for (i, p) in priorities.iter_mut().enumerate() {
+            *p = i as u8;
+        }
+
+        Pic {
+            mask: 0,
+            vector_base: DEFAULT_PIC_VECTOR_BASE,
+            trigger: DEFAULT_PIC_TRIGGER,
+            priorities,
+            requested: 0,
+            in_service: 0,
+            levels: 0,
+            previous: 0
+        }
+    }
+
+    /// Drives `line` high for the current tick.
+    pub fn assert(&mut self, line: u8) {
+        if line < PIC_LINES {
+            self.levels |= 1 << line;
+        }
+    }
+
+    /// Starts a tick, during which devices assert the lines they hold high.
+    pub fn begin_tick(&mut self) {
+        self.previous = self.levels;
+        self.levels = 0;
+    }
+
+    /// Turns the lines asserted during the tick into requests.
+    pub fn end_tick(&mut self) {
+        let edges = self.levels & !self.previous;
+        self.requested = (self.requested | edges) & !self.trigger | (self.levels & self.trigger);
+    }
+
+    /// The line which would interrupt the CPU now, if any.
+    fn next_line(&self) -> Option<u8> {
+        let best = |lines: u8| (0..PIC_LINES).filter(|&l| lines & (1 << l) != 0)
+                                             .min_by_key(|&l| (self.priorities[l as usize], l));
+
+        let line = best(self.requested & !self.mask)?;
+
+        match best(self.in_service) {
+            Some(s) if (self.priorities[s as usize], s) <= (self.priorities[line as usize], line) => None,
+            _ => Some(line)
+        }
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.next_line().is_some()
+    }
+
+    /// Puts the next interrupting line in service, returning its vector.
+    pub fn acknowledge(&mut self) -> Option<u8> {
+        let line = self.next_line()?;
+
+        self.requested &= !(1 << line);
+        self.in_service |= 1 << line;
+        debug!("PIC: acknowledged IRQ {}", line);
+
+        Some(self.vector_base.wrapping_add(line))
+    }
+
+    /// Ends the in-service interrupt with the best priority.
+    pub fn end_of_interrupt(&mut self) {
+        let in_service = self.in_service;
+        let line = (0..PIC_LINES).filter(|&l| in_service & (1 << l) != 0)
+                                 .min_by_key(|&l| (self.priorities[l as usize], l));
+
+        if let Some(line) = line {
+            self.in_service &= !(1 << line);
+        }
+    }
+
+    pub fn read(&self, port: u32) -> u8 {
+        match port {
+            PIC_COMMAND => self.in_service,
+            PIC_MASK => self.mask,
+            PIC_VECTOR_BASE => self.vector_base,
+            PIC_TRIGGER => self.trigger,
+            PIC_REQUESTED => self.requested,
+            _ => self.priorities[(port - PIC_PRIORITY) as usize]
+        }
+    }
+
+    pub fn write(&mut self, port: u32, val: u8) {
+        match port {
+            PIC_COMMAND => if val == PIC_EOI { self.end_of_interrupt() },
+            PIC_MASK => self.mask = val,
+            PIC_VECTOR_BASE => self.vector_base = val,
+            PIC_TRIGGER => self.trigger = val,
+            PIC_REQUESTED => {},
+            _ => self.priorities[(port - PIC_PRIORITY) as usize] = val
+        }
+    }
+}
+
+impl Default for Pic {
+    fn default() -> Pic {
+        Pic::new()
+    }
+}
@@ -0,0 +1,217 @@
+//! Port I/O, for the IN, INS, OUT and OUTS instructions.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use error::VmError;
+use pic::{Pic, PIC_PORT, PIC_PORTS};
+use reset::{ResetControl, ResetKind, RESET_PORT};
+
+/// A device which claims a range of I/O ports. Ports are numbered relative
+/// to the first port of the range it is registered at.
+pub trait PortDevice {
+    fn read_short(&mut self, port: u32) -> u8;
+    fn write_short(&mut self, port: u32, val: u8);
+
+    /// Reads a long. The default reads a byte and zero-extends it.
+    fn read_long(&mut self, port: u32) -> u32 {
+        self.read_short(port) as u32
+    }
+
+    /// Writes a long. The default writes its low byte.
+    fn write_long(&mut self, port: u32, val: u32) {
+        self.write_short(port, val as u8)
+    }
+
+    /// Called after every instruction the CPU retires. Devices request
+    /// interrupts by asserting their lines of `pic`.
+    fn tick(&mut self, _pic: &mut Pic) {}
+
+    /// Called when the CPU is reset, to put the device back in its
+    /// power-on state.
+    fn reset(&mut self) {}
+}
+
+/// What happens on accesses to ports no device has claimed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnclaimedPorts {
+    /// Reads return all ones, as from a floating bus, and writes are
+    /// ignored.
+    Open,
+    /// The access raises a PROTECT interrupt.
+    Fault
+}
+
+pub struct PortBus {
+    devices: Vec<(u32, Box<dyn PortDevice>)>,
+    /// Index into `devices` of the device claiming each port.
+    ports: HashMap<u32, usize>,
+    pub unclaimed: UnclaimedPorts,
+    /// The interrupt controller, which always claims the ports from
+    /// `PIC_PORT`.
+    pub pic: Pic,
+    /// Reset requested through the `ResetControl` at `RESET_PORT`.
+    reset: Rc<Cell<Option<ResetKind>>>
+}
+
+impl PortBus {
+    /// Creates a port bus with just the interrupt controller and the reset
+    /// control at `RESET_PORT`.
+    pub fn new() -> PortBus {
+        let reset = Rc::new(Cell::new(None));
+        let control: Box<dyn PortDevice> = Box::new(ResetControl::new(reset.clone()));
+        let mut ports = HashMap::new();
+        ports.insert(RESET_PORT, 0);
+
+        PortBus {
+            devices: vec![(RESET_PORT, control)],
+            ports,
+            unclaimed: UnclaimedPorts::Open,
+            pic: Pic::new(),
+            reset
+        }
+    }
+
+    /// Takes the reset requested by the guest since the last call, if any.
+    pub fn take_reset(&mut self) -> Option<ResetKind> {
+        self.reset.take()
+    }
+
+    /// Gives `device` the `count` ports starting at `base`.
+    pub fn register(&mut self, base: u32, count: u32, device: Box<dyn PortDevice>)
+            -> Result<(), VmError> {
+        let taken = count == 0 || base.checked_add(count - 1).is_none()
+            || (0..count).any(|i| self.ports.contains_key(&(base + i)) || is_pic_port(base + i));
+
+        if taken {
+            return Err(VmError::PortsTaken { base, count });
+        }
+
+        let index = self.devices.len();
+        self.devices.push((base, device));
+
+        for i in 0..count {
+            self.ports.insert(base + i, index);
+        }
+
+        Ok(())
+    }
+
+    /// Finds the device claiming `port`, along with the port's number
+    /// relative to that device.
+    fn find(&mut self, port: u32) -> Option<(&mut Box<dyn PortDevice>, u32)> {
+        let index = *self.ports.get(&port)?;
+        let &mut (base, ref mut device) = &mut self.devices[index];
+
+        Some((device, port - base))
+    }
+
+    /// Reads a byte from `port`, or returns `None` if the access faults.
+    pub fn read_short(&mut self, port: u32) -> Option<u8> {
+        if is_pic_port(port) {
+            return Some(self.pic.read(port - PIC_PORT));
+        }
+
+        match self.find(port) {
+            Some((device, p)) => Some(device.read_short(p)),
+            None => self.read_unclaimed(port).map(|v| v as u8)
+        }
+    }
+
+    /// Reads a long from `port`, or returns `None` if the access faults.
+    pub fn read_long(&mut self, port: u32) -> Option<u32> {
+        if is_pic_port(port) {
+            return Some(self.pic.read(port - PIC_PORT) as u32);
+        }
+
+        match self.find(port) {
+            Some((device, p)) => Some(device.read_long(p)),
+            None => self.read_unclaimed(port)
+        }
+    }
+
+    /// Writes a byte to `port`, returning false if the access faults.
+    pub fn write_short(&mut self, port: u32, val: u8) -> bool {
+        if is_pic_port(port) {
+            self.pic.write(port - PIC_PORT, val);
+            return true;
+        }
+
+        match self.find(port) {
+            Some((device, p)) => {
+                device.write_short(p, val);
+                true
+            }
+            None => self.write_unclaimed(port, val as u32)
+        }
+    }
+
+    /// Writes a long to `port`, returning false if the access faults.
+    pub fn write_long(&mut self, port: u32, val: u32) -> bool {
+        if is_pic_port(port) {
+            self.pic.write(port - PIC_PORT, val as u8);
+            return true;
+        }
+
+        match self.find(port) {
+            Some((device, p)) => {
+                device.write_long(p, val);
+                true
+            }
+            None => self.write_unclaimed(port, val)
+        }
+    }
+
+    /// Ticks every device, then updates the interrupt controller with the
+    /// lines they asserted.
+    pub fn tick(&mut self) {
+        self.pic.begin_tick();
+
+        for &mut (_, ref mut device) in self.devices.iter_mut() {
+            device.tick(&mut self.pic);
+        }
+
+        self.pic.end_tick();
+    }
+
+    /// Resets the interrupt controller and every device, dropping any
+    /// requested reset.
+    pub fn reset(&mut self) {
+        self.pic = Pic::new();
+        self.reset.set(None);
+
+        for &mut (_, ref mut device) in self.devices.iter_mut() {
+            device.reset();
+        }
+    }
+
+    fn read_unclaimed(&self, port: u32) -> Option<u32> {
+        debug!("Read from unclaimed port 0x{:X}", port);
+
+        match self.unclaimed {
+            UnclaimedPorts::Open => Some(0xFFFF_FFFF),
+            UnclaimedPorts::Fault => None
+        }
+    }
+
+    fn write_unclaimed(&self, port: u32, val: u32) -> bool {
+        match self.unclaimed {
+            UnclaimedPorts::Open => {
+                debug!("Write of 0x{:X} to unclaimed port 0x{:X}", val, port);
+                true
+            }
+            UnclaimedPorts::Fault => false
+        }
+    }
+}
+
+fn is_pic_port(port: u32) -> bool {
+    port >= PIC_PORT && port - PIC_PORT < PIC_PORTS
+}
+
+impl Default for PortBus {
+    fn default() -> PortBus {
+        PortBus::new()
+    }
+}
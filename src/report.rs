@@ -0,0 +1,129 @@
+use std::fmt;
+
+use cpu::Cpu;
+use mem::Mem;
+use flag::{Flag, FLAG_NAMES};
+use disasm::Disassembler;
+
+/// Number of bytes shown before rp, since we can't disassemble backwards.
+const BYTES_BEFORE_RP: u32 = 16;
+/// Number of instructions disassembled starting at rp.
+const INSTRUCTIONS_AFTER_RP: usize = 6;
+/// Number of stack words shown, starting at r15.
+const STACK_WORDS: u32 = 8;
+
+/// The full architectural state of a CPU, for printing when it stops
+/// abnormally.
+pub struct CrashReport<'a> {
+    cpu: &'a Cpu
+}
+
+impl<'a> CrashReport<'a> {
+    pub fn new(cpu: &'a Cpu) -> CrashReport<'a> {
+        CrashReport { cpu }
+    }
+
+    fn fmt_flags(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Flags:")?;
+
+        for &(mask, name) in FLAG_NAMES.iter() {
+            if self.cpu.flag_get(mask) {
+                write!(f, " {}", name)?;
+            }
+        }
+
+        writeln!(f)
+    }
+
+    fn fmt_interrupts(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cpu = self.cpu;
+        write!(f, "Pending interrupts:")?;
+
+        if let Some(addr) = cpu.mem_interrupt_address {
+            let name = if cpu.mem_misaligned { "ALIGNMENT" } else { "MEMORY" };
+            write!(f, " {}@0x{:X}", name, addr)?;
+
+            if let Some(code) = cpu.mem_fault_code {
+                write!(f, " (code {})", code)?;
+            }
+        }
+
+        if cpu.instr_interrupt {
+            write!(f, " INSTRUCTION")?;
+        }
+
+        if cpu.protect_interrupt {
+            write!(f, " PROTECT")?;
+        }
+
+        write!(f, " queue={:?}", cpu.interrupt_queue)?;
+        write!(f, " irq requested=0x{:02X} in-service=0x{:02X} masked=0x{:02X}",
+               cpu.ports.pic.requested, cpu.ports.pic.in_service, cpu.ports.pic.mask)?;
+
+        if let Some(status) = cpu.halted {
+            write!(f, " (halted with status {})", status)?;
+        }
+
+        writeln!(f)
+    }
+
+    fn fmt_code(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rp = self.cpu.rp;
+        let start = rp.saturating_sub(BYTES_BEFORE_RP);
+
+        write!(f, "Code before rp: 0x{:08X}:", start)?;
+
+        for addr in start..rp {
+            match self.cpu.mem_peek_short(addr) {
+                Some(b) => write!(f, " {:02X}", b)?,
+                None => write!(f, " ??")?
+            }
+        }
+
+        writeln!(f)?;
+
+        let mut disasm = Disassembler::new(self.cpu, rp);
+
+        for i in 0..INSTRUCTIONS_AFTER_RP {
+            let marker = if i == 0 { "=>" } else { "  " };
+
+            match disasm.next_instruction() {
+                Some(instr) => writeln!(f, "{} {}", marker, instr)?,
+                None => {
+                    writeln!(f, "{} 0x{:08X}: <invalid>", marker, disasm.addr)?;
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fmt_stack(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Stack:")?;
+
+        for i in 0..STACK_WORDS {
+            let addr = self.cpu.reg[15].wrapping_add(i * 4);
+
+            match self.cpu.mem_peek_long(addr) {
+                Some(word) => writeln!(f, "  0x{:08X}: 0x{:08X}", addr, word)?,
+                None => {
+                    writeln!(f, "  0x{:08X}: <out of bounds>", addr)?;
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for CrashReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.cpu)?;
+        self.fmt_flags(f)?;
+        self.fmt_interrupts(f)?;
+        self.fmt_code(f)?;
+        self.fmt_stack(f)
+    }
+}
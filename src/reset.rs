@@ -0,0 +1,120 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use cpu::Cpu;
+use default::*;
+use port::PortDevice;
+
+/// Port a guest writes to in order to reset the machine. The port bus
+/// always claims it with a `ResetControl`.
+pub const RESET_PORT: u32 = 0xCF9;
+/// Set in a value written to `RESET_PORT` to reset the CPU.
+pub const RESET_CPU_BIT: u32 = 0b100;
+/// Set alongside `RESET_CPU_BIT` to make the reset a cold one.
+pub const RESET_COLD_BIT: u32 = 0b1000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResetKind {
+    /// Like cycling the power: memory is also cleared if the power-on state
+    /// asks for it.
+    Cold,
+    /// Registers and pending interrupts are reset, memory is left as-is.
+    Warm
+}
+
+/// The state the CPU is put in by a reset.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PowerOnState {
+    /// Address of the first instruction executed after a reset.
+    pub reset_vector: u32,
+    /// Initial value of the flags register.
+    pub rflags: u32,
+    /// Initial stack pointer, r15.
+    pub stack_pointer: u32,
+    /// Whether a cold reset zeroes memory.
+    pub clear_memory: bool
+}
+
+impl Default for PowerOnState {
+    fn default() -> PowerOnState {
+        PowerOnState {
+            reset_vector: DEFAULT_RESET_VECTOR,
+            rflags: DEFAULT_RFLAGS,
+            stack_pointer: DEFAULT_STACK_POINTER,
+            clear_memory: false
+        }
+    }
+}
+
+impl ResetKind {
+    /// Decodes a value written to `RESET_PORT`, if it requests a reset.
+    pub fn from_port_value(val: u32) -> Option<ResetKind> {
+        if val & RESET_CPU_BIT == 0 {
+            None
+        } else if val & RESET_COLD_BIT != 0 {
+            Some(ResetKind::Cold)
+        } else {
+            Some(ResetKind::Warm)
+        }
+    }
+}
+
+/// The device at `RESET_PORT`. Writes of either width which request a reset
+/// leave it pending until the CPU has finished the current instruction.
+pub struct ResetControl {
+    requested: Rc<Cell<Option<ResetKind>>>
+}
+
+impl ResetControl {
+    /// Creates the device, which records requested resets in `requested`.
+    pub fn new(requested: Rc<Cell<Option<ResetKind>>>) -> ResetControl {
+        ResetControl { requested }
+    }
+}
+
+impl PortDevice for ResetControl {
+    fn read_short(&mut self, _port: u32) -> u8 {
+        0
+    }
+
+    fn write_short(&mut self, _port: u32, val: u8) {
+        if let Some(kind) = ResetKind::from_port_value(val as u32) {
+            self.requested.set(Some(kind));
+        }
+    }
+}
+
+pub trait Reset {
+    fn reset(&mut self, kind: ResetKind);
+}
+
+impl Reset for Cpu {
+    fn reset(&mut self, kind: ResetKind) {
+        debug!("{:?} reset.", kind);
+
+        let state = self.power_on;
+
+        self.reg = [0; 16];
+        self.reg[15] = state.stack_pointer;
+        self.rflags = state.rflags;
+        self.rm = 0;
+        self.ri = 0;
+        self.rp = state.reset_vector;
+        self.rks = 0;
+        self.rkt = 0;
+        self.rf = 0;
+        self.mem_interrupt_address = None;
+        self.mem_fault_code = None;
+        self.mem_misaligned = false;
+        self.instr_interrupt = false;
+        self.protect_interrupt = false;
+        self.interrupt_queue.clear();
+        self.ports.reset();
+        self.halted = None;
+        self.tlb.invalidate();
+
+        if kind == ResetKind::Cold && state.clear_memory {
+            self.bus.clear();
+        }
+    }
+}
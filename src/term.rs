@@ -0,0 +1,58 @@
+//! Puts the host terminal into raw mode while the guest owns the serial
+//! console, so it sees each key as it is typed and does its own echoing.
+
+use libc;
+
+/// The terminal settings to restore on exit. Only written before the exit
+/// handlers are installed, so they can read it without locking.
+static mut ORIGINAL: Option<libc::termios> = None;
+
+/// Switches stdin, if it is a terminal, to raw mode until the process exits.
+/// Ctrl-C still stops the emulator.
+pub fn enter_raw_mode() {
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) == 0 {
+            return;
+        }
+
+        let mut termios: libc::termios = ::std::mem::zeroed();
+
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+            warn!("Cannot read terminal settings, leaving the terminal as is.");
+            return;
+        }
+
+        ORIGINAL = Some(termios);
+
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+
+        libc::atexit(restore_at_exit);
+        libc::signal(libc::SIGINT, restore_on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, restore_on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+
+        if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+            warn!("Cannot put the terminal into raw mode.");
+        }
+    }
+}
+
+fn restore() {
+    unsafe {
+        if let Some(termios) = ORIGINAL {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+        }
+    }
+}
+
+extern "C" fn restore_at_exit() {
+    restore();
+}
+
+extern "C" fn restore_on_signal(signal: libc::c_int) {
+    restore();
+
+    // Only async-signal-safe calls from here, so no `process::exit`.
+    unsafe { libc::_exit(128 + signal) }
+}
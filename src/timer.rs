@@ -0,0 +1,108 @@
+//! A programmable interval timer which counts retired instructions.
+//!
+//! It claims `TIMER_PORTS` ports from wherever it is registered (the CLI
+//! uses `TIMER_PORT`):
+//!
+//! * `TIMER_CONTROL`: `TIMER_ENABLE` and `TIMER_PERIODIC` bits. Enabling the
+//!   timer (re)starts the countdown from the reload value.
+//! * `TIMER_RELOAD`: number of instructions until the timer expires. Reads
+//!   give the instructions left in the current countdown.
+//! * `TIMER_LINE`: IRQ line asserted on expiry.
+//!
+//! Any other ports it is given read as 0 and ignore writes.
+//!
+//! On expiry a one-shot timer disables itself, and a periodic one starts
+//! counting down again.
+
+use port::PortDevice;
+use pic::Pic;
+use default::DEFAULT_TIMER_LINE;
+
+pub const TIMER_PORT: u32 = 0x40;
+pub const TIMER_PORTS: u32 = 3;
+
+pub const TIMER_CONTROL: u32 = 0;
+pub const TIMER_RELOAD: u32 = 1;
+pub const TIMER_LINE: u32 = 2;
+
+pub const TIMER_ENABLE: u8 = 0b1;
+pub const TIMER_PERIODIC: u8 = 0b10;
+
+pub struct Timer {
+    control: u8,
+    reload: u32,
+    count: u32,
+    line: u8
+}
+
+impl Timer {
+    /// Creates a disabled timer.
+    pub fn new() -> Timer {
+        Timer {
+            control: 0,
+            reload: 0,
+            count: 0,
+            line: DEFAULT_TIMER_LINE
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Timer {
+        Timer::new()
+    }
+}
+
+impl PortDevice for Timer {
+    fn read_short(&mut self, port: u32) -> u8 {
+        self.read_long(port) as u8
+    }
+
+    fn write_short(&mut self, port: u32, val: u8) {
+        self.write_long(port, val as u32)
+    }
+
+    fn read_long(&mut self, port: u32) -> u32 {
+        match port {
+            TIMER_CONTROL => self.control as u32,
+            TIMER_RELOAD => self.count,
+            TIMER_LINE => self.line as u32,
+            _ => 0
+        }
+    }
+
+    fn write_long(&mut self, port: u32, val: u32) {
+        match port {
+            TIMER_CONTROL => {
+                self.control = val as u8 & (TIMER_ENABLE | TIMER_PERIODIC);
+                self.count = self.reload;
+            }
+            TIMER_RELOAD => self.reload = val,
+            TIMER_LINE => self.line = val as u8,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Timer::new();
+    }
+
+    fn tick(&mut self, pic: &mut Pic) {
+        if self.control & TIMER_ENABLE == 0 || self.count == 0 {
+            return;
+        }
+
+        self.count -= 1;
+
+        if self.count == 0 {
+            debug!("Timer expired.");
+            pic.assert(self.line);
+
+            if self.control & TIMER_PERIODIC != 0 {
+                self.count = self.reload;
+            } else {
+                self.control &= !TIMER_ENABLE;
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+
+/// A cached translation of one virtual page.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TlbEntry {
+    /// Virtual page number.
+    pub vpn: u32,
+    /// Physical address of the page frame.
+    pub frame: u32,
+    /// Effective permissions from the page walk.
+    pub permissions: u32,
+    /// Physical addresses of the directory and table entries used.
+    pub entries: (u32, u32)
+}
+
+/// A direct-mapped translation lookaside buffer. Entries are only ever
+/// dropped by a flush, so editing the page tables without one leaves stale
+/// translations in place, just like real hardware.
+pub struct Tlb {
+    entries: Vec<Option<TlbEntry>>,
+    pub hits: u64,
+    pub misses: u64,
+    pub flushes: u64
+}
+
+impl Tlb {
+    /// Creates a TLB with `size` entries. A size of 0 disables it.
+    pub fn new(size: usize) -> Tlb {
+        Tlb {
+            entries: vec![None; size],
+            hits: 0,
+            misses: 0,
+            flushes: 0
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.entries.is_empty()
+    }
+
+    pub fn lookup(&mut self, vpn: u32) -> Option<TlbEntry> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let index = vpn as usize % self.entries.len();
+
+        match self.entries[index] {
+            Some(entry) if entry.vpn == vpn => {
+                self.hits += 1;
+                Some(entry)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, entry: TlbEntry) {
+        if self.is_enabled() {
+            let index = entry.vpn as usize % self.entries.len();
+            self.entries[index] = Some(entry);
+        }
+    }
+
+    /// Drops every entry on behalf of the guest, counting the flush.
+    pub fn flush(&mut self) {
+        debug!("TLB flush.");
+        self.flushes += 1;
+        self.invalidate();
+    }
+
+    /// Drops every entry without touching the statistics, as on reset.
+    pub fn invalidate(&mut self) {
+        for e in self.entries.iter_mut() {
+            *e = None;
+        }
+    }
+}
+
+impl fmt::Display for Tlb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TLB ({} entries): {} hits, {} misses, {} flushes",
+               self.entries.len(), self.hits, self.misses, self.flushes)
+    }
+}
@@ -0,0 +1,136 @@
+//! A compact binary log of every guest memory access.
+//!
+//! A trace file starts with the 4-byte magic `VTRC` and a version byte,
+//! followed by one fixed-size little-endian record per access:
+//!
+//! | bytes | field                                  |
+//! |-------|----------------------------------------|
+//! | 8     | instructions retired before the access |
+//! | 4     | virtual address                        |
+//! | 4     | physical address                       |
+//! | 1     | width in bytes                         |
+//! | 1     | kind (see `TraceKind`)                 |
+
+use std::convert::TryInto;
+use std::io::{self, Read, Write, BufWriter};
+
+pub const TRACE_MAGIC: [u8; 4] = *b"VTRC";
+pub const TRACE_VERSION: u8 = 1;
+/// Size in bytes of each record.
+pub const RECORD_SIZE: usize = 18;
+
+/// What a traced access was for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceKind {
+    Fetch = 0,
+    Read = 1,
+    Write = 2,
+    Push = 3,
+    Pop = 4
+}
+
+impl TraceKind {
+    pub fn from_u8(kind: u8) -> Option<TraceKind> {
+        match kind {
+            0 => Some(TraceKind::Fetch),
+            1 => Some(TraceKind::Read),
+            2 => Some(TraceKind::Write),
+            3 => Some(TraceKind::Push),
+            4 => Some(TraceKind::Pop),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub instruction: u64,
+    pub va: u32,
+    pub pa: u32,
+    pub width: u8,
+    pub kind: TraceKind
+}
+
+impl TraceRecord {
+    fn to_bytes(self) -> [u8; RECORD_SIZE] {
+        let mut b = [0; RECORD_SIZE];
+
+        b[0..8].copy_from_slice(&self.instruction.to_le_bytes());
+        b[8..12].copy_from_slice(&self.va.to_le_bytes());
+        b[12..16].copy_from_slice(&self.pa.to_le_bytes());
+        b[16] = self.width;
+        b[17] = self.kind as u8;
+        b
+    }
+
+    fn from_bytes(b: &[u8; RECORD_SIZE]) -> Option<TraceRecord> {
+        Some(TraceRecord {
+            instruction: u64::from_le_bytes(b[0..8].try_into().unwrap()),
+            va: u32::from_le_bytes(b[8..12].try_into().unwrap()),
+            pa: u32::from_le_bytes(b[12..16].try_into().unwrap()),
+            width: b[16],
+            kind: TraceKind::from_u8(b[17])?
+        })
+    }
+}
+
+/// Writes a trace to any byte sink. Output is buffered, so call `finish`
+/// once done.
+pub struct TraceWriter {
+    out: BufWriter<Box<dyn Write>>
+}
+
+impl TraceWriter {
+    pub fn new<W: Write + 'static>(out: W) -> io::Result<TraceWriter> {
+        let mut out = BufWriter::new(Box::new(out) as Box<dyn Write>);
+        out.write_all(&TRACE_MAGIC)?;
+        out.write_all(&[TRACE_VERSION])?;
+
+        Ok(TraceWriter { out })
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        self.out.write_all(&record.to_bytes())
+    }
+
+    /// Flushes any buffered records.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Reads back a trace written by `TraceWriter`, one record at a time.
+pub struct TraceReader<R: Read> {
+    input: R
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Checks the header of the trace in `input`.
+    pub fn new(mut input: R) -> io::Result<TraceReader<R>> {
+        let mut header = [0; 5];
+        input.read_exact(&mut header)?;
+
+        if header[..4] != TRACE_MAGIC || header[4] != TRACE_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a vesta trace"));
+        }
+
+        Ok(TraceReader { input })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<io::Result<TraceRecord>> {
+        let mut b = [0; RECORD_SIZE];
+
+        match self.input.read_exact(&mut b) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e))
+        }
+
+        Some(TraceRecord::from_bytes(&b)
+             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad trace record kind")))
+    }
+}
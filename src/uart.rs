@@ -0,0 +1,153 @@
+//! A serial port, for talking to the guest over a console.
+//!
+//! It claims `UART_PORTS` ports from wherever it is registered (the CLI
+//! uses `UART_PORT`):
+//!
+//! * `UART_DATA`: writes transmit a byte; reads take the next received
+//!   byte, or 0 if there is none.
+//! * `UART_INTERRUPT_ENABLE`: `UART_RX_INTERRUPT` and `UART_TX_INTERRUPT`
+//!   bits, saying which conditions assert the UART's IRQ line.
+//! * `UART_STATUS`: `UART_RX_READY` while received data is waiting, and
+//!   `UART_TX_EMPTY` since transmission is instant. Reading it acknowledges
+//!   a TX-empty interrupt.
+//!
+//! Any other ports it is given read as 0 and ignore writes.
+//!
+//! The IRQ line is held high for as long as an enabled condition holds, so
+//! it needs to be level-triggered, as `DEFAULT_UART_LINE` is out of reset.
+//! A TX-empty interrupt is raised after each transmitted byte, and when the
+//! interrupt is first enabled.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use port::PortDevice;
+use pic::Pic;
+use default::DEFAULT_UART_LINE;
+
+pub const UART_PORT: u32 = 0x3F8;
+pub const UART_PORTS: u32 = 3;
+
+pub const UART_DATA: u32 = 0;
+pub const UART_INTERRUPT_ENABLE: u32 = 1;
+pub const UART_STATUS: u32 = 2;
+
+pub const UART_RX_INTERRUPT: u8 = 0b1;
+pub const UART_TX_INTERRUPT: u8 = 0b10;
+
+pub const UART_RX_READY: u8 = 0b1;
+pub const UART_TX_EMPTY: u8 = 0b10;
+
+pub struct Uart {
+    input: Option<Receiver<u8>>,
+    output: Box<dyn Write>,
+    received: VecDeque<u8>,
+    interrupt_enable: u8,
+    /// A TX-empty interrupt has not been acknowledged yet.
+    tx_pending: bool,
+    /// IRQ line asserted by the UART.
+    pub line: u8
+}
+
+impl Uart {
+    /// Creates a UART which receives bytes sent down `input`, if any, and
+    /// transmits them to `output`.
+    pub fn new(input: Option<Receiver<u8>>, output: Box<dyn Write>) -> Uart {
+        Uart {
+            input,
+            output,
+            received: VecDeque::new(),
+            interrupt_enable: 0,
+            tx_pending: false,
+            line: DEFAULT_UART_LINE
+        }
+    }
+
+    /// Moves any bytes which have arrived into the receive buffer.
+    fn poll_input(&mut self) {
+        if let Some(ref input) = self.input {
+            self.received.extend(input.try_iter());
+        }
+    }
+
+    fn status(&self) -> u8 {
+        let rx = if self.received.is_empty() { 0 } else { UART_RX_READY };
+        rx | UART_TX_EMPTY
+    }
+}
+
+/// Spawns a thread which reads host stdin and sends it to the returned
+/// receiver, a byte at a time, for `Uart::new`.
+pub fn stdin_reader() -> Receiver<u8> {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut buf = [0; 256];
+
+        while let Ok(n) = stdin.lock().read(&mut buf) {
+            if n == 0 || buf[..n].iter().any(|&b| tx.send(b).is_err()) {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+impl PortDevice for Uart {
+    fn read_short(&mut self, port: u32) -> u8 {
+        self.poll_input();
+
+        match port {
+            UART_DATA => self.received.pop_front().unwrap_or(0),
+            UART_INTERRUPT_ENABLE => self.interrupt_enable,
+            UART_STATUS => {
+                self.tx_pending = false;
+                self.status()
+            }
+            _ => 0
+        }
+    }
+
+    fn write_short(&mut self, port: u32, val: u8) {
+        match port {
+            UART_DATA => {
+                if let Err(e) = self.output.write_all(&[val]).and_then(|_| self.output.flush()) {
+                    warn!("Cannot write serial output: {}", e);
+                }
+
+                self.tx_pending = true;
+            }
+            UART_INTERRUPT_ENABLE => {
+                if val & UART_TX_INTERRUPT != 0 && self.interrupt_enable & UART_TX_INTERRUPT == 0 {
+                    self.tx_pending = true;
+                }
+
+                self.interrupt_enable = val & (UART_RX_INTERRUPT | UART_TX_INTERRUPT);
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, pic: &mut Pic) {
+        self.poll_input();
+
+        let rx = self.interrupt_enable & UART_RX_INTERRUPT != 0 && !self.received.is_empty();
+        let tx = self.interrupt_enable & UART_TX_INTERRUPT != 0 && self.tx_pending;
+
+        if rx || tx {
+            pic.assert(self.line);
+        }
+    }
+
+    /// Disables interrupts and drops anything received but not yet read.
+    fn reset(&mut self) {
+        self.poll_input();
+        self.received.clear();
+        self.interrupt_enable = 0;
+        self.tx_pending = false;
+    }
+}
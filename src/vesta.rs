@@ -1,28 +1,137 @@
 extern crate getopts;
 use getopts::Options;
 
+extern crate libc;
+
 extern crate simplelog;
-use simplelog::{TermLogger, Config, LogLevelFilter};
+use simplelog::{TermLogger, Config, ConfigBuilder, LevelFilter, TerminalMode, ColorChoice};
 
 #[macro_use]
 extern crate log;
 
+extern crate vesta;
+use vesta::default::*;
+use vesta::{Cpu, StopReason, RunLimits, TripleFaultAction};
+use vesta::{Reset, ResetKind, PowerOnState, CrashReport, VmError, Tlb};
+use vesta::{MemoryMap, RomWrites, AlignmentPolicy, Watchpoint, TraceWriter};
+use vesta::{CacheConfig, CacheHierarchy, ReplacementPolicy, UnclaimedPorts, Timer, Uart};
+use vesta::timer::{TIMER_PORT, TIMER_PORTS};
+use vesta::uart::{self, UART_PORT, UART_PORTS};
+
 #[macro_use]
 mod debug;
 use debug::*;
 
-mod default;
-use default::*;
+mod term;
+
+/// Exit status used when a runaway guest is stopped by a watchdog.
+const WATCHDOG_EXIT_STATUS: i32 = 124;
+/// Exit status used when the guest is stopped by a watchpoint.
+const WATCHPOINT_EXIT_STATUS: i32 = 125;
+
+/// Exit status for a guest which halted with a non-zero `status`. Only the
+/// low byte reaches the host, so a status which would read as success or as
+/// one of the statuses above becomes 1 instead.
+fn halt_exit_status(status: u32) -> i32 {
+    match (status & 0xFF) as i32 {
+        0 | WATCHDOG_EXIT_STATUS | WATCHPOINT_EXIT_STATUS => 1,
+        code => code
+    }
+}
 
-mod cpu;
-use cpu::Cpu;
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+fn parse_number(s: &str) -> Result<u32, ::std::num::ParseIntError> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        u32::from_str_radix(&s[2..], 16)
+    } else {
+        s.parse()
+    }
+}
+
+/// Parses a size in bytes, given like a number to `parse_number` or in
+/// decimal with a `K`, `M` or `G` (binary) suffix.
+fn parse_size(s: &str) -> Option<u64> {
+    let shift = match s.chars().last()? {
+        'K' | 'k' => 10,
+        'M' | 'm' => 20,
+        'G' | 'g' => 30,
+        _ => return parse_number(s).ok().map(|n| n as u64)
+    };
 
-mod wrapping_util;
-mod operation;
-mod interrupt;
-mod flag;
-mod mem;
-mod execute;
+    let n: u64 = s[..s.len() - 1].parse().ok()?;
+    n.checked_mul(1 << shift)
+}
+
+/// Splits a `BASE:REST` region argument.
+fn parse_region(s: &str) -> Option<(u32, &str)> {
+    let mut parts = s.splitn(2, ':');
+    let base = parse_number(parts.next()?).ok()?;
+
+    Some((base, parts.next()?))
+}
+
+/// Parses a `ADDR[:LEN][:rwx]` watchpoint, which defaults to watching
+/// writes to a single byte.
+fn parse_watchpoint(s: &str) -> Option<Watchpoint> {
+    let is_kinds = |p: &str| !p.is_empty() && p.chars().all(|c| "rwx".contains(c));
+    let mut parts = s.split(':');
+
+    let addr = parse_number(parts.next()?).ok()?;
+    let mut len = 1;
+    let mut kinds = "w";
+
+    for part in parts {
+        if is_kinds(part) {
+            kinds = part;
+        } else {
+            len = parse_number(part).ok()?;
+        }
+    }
+
+    Some(Watchpoint {
+        addr,
+        len,
+        read: kinds.contains('r'),
+        write: kinds.contains('w'),
+        execute: kinds.contains('x')
+    })
+}
+
+/// Parses a `SIZE:ASSOC:LINE[:lru|fifo|random]` cache description.
+fn parse_cache(s: &str) -> Option<CacheConfig> {
+    let parts: Vec<&str> = s.split(':').collect();
+
+    if parts.len() < 3 || parts.len() > 4 {
+        return None;
+    }
+
+    let policy = match parts.get(3) {
+        None | Some(&"lru") => ReplacementPolicy::Lru,
+        Some(&"fifo") => ReplacementPolicy::Fifo,
+        Some(&"random") => ReplacementPolicy::Random,
+        Some(_) => return None
+    };
+
+    let size = parse_size(parts[0])?;
+
+    Some(CacheConfig {
+        size: if size <= u32::MAX as u64 { size as u32 } else { return None },
+        associativity: parse_number(parts[1]).ok()?,
+        line_size: parse_size(parts[2])? as u32,
+        policy
+    })
+}
+
+/// Reports statistics gathered while the guest ran.
+fn print_stats(cpu: &Cpu) {
+    if cpu.tlb.hits + cpu.tlb.misses > 0 {
+        info!("{}", cpu.tlb);
+    }
+
+    if let Some(ref caches) = cpu.caches {
+        info!("{}", caches);
+    }
+}
 
 fn print_usage(opts: Options) -> ! {
     let brief = &"Usage: vesta KERNEL [options]";
@@ -31,40 +140,166 @@ fn print_usage(opts: Options) -> ! {
 
 fn main() {
     use std::env;
+    use std::fs;
+    use std::io;
+    use std::process;
+    use std::time::Duration;
     let args: Vec<String> = env::args().collect();
 
     let mut opts = Options::new();
     opts.optflag("h", "help", "Print this help menu");
-    opts.optopt("M", "memsize", "Memory size (in bytes) for the CPU to use as RAM", "SIZE");
+    opts.optopt("M", "memsize", "Memory size (in bytes, or with a K, M or G suffix) for the CPU \
+                                 to use as RAM, up to 4G", "SIZE");
     opts.optflag("D", "debug", "Print extremely verbose debug output");
+    opts.optopt("", "max-instructions", "Stop after executing N instructions", "N");
+    opts.optopt("", "timeout", "Stop after running for SECS seconds", "SECS");
+    opts.optopt("", "triple-fault", "What to do on a triple fault (default: shutdown)",
+                "reset|shutdown");
+    opts.optopt("", "reset-vector", "Address execution starts from after a reset", "ADDR");
+    opts.optopt("", "stack", "Initial stack pointer (r15) after a reset", "ADDR");
+    opts.optopt("", "rflags", "Initial flags register after a reset", "FLAGS");
+    opts.optflag("", "clear-memory", "Zero memory on a cold reset");
+    opts.optopt("", "tlb-size", "Number of TLB entries, or 0 to disable the TLB", "N");
+    opts.optmulti("", "ram", "Map SIZE more bytes of RAM at BASE", "BASE:SIZE");
+    opts.optmulti("", "rom", "Map the contents of FILE as ROM at BASE", "BASE:FILE");
+    opts.optopt("", "rom-writes", "What writes to ROM do (default: fault)", "fault|ignore");
+    opts.optflag("", "show-map", "Print the memory map at startup");
+    opts.optmulti("", "watch", "Stop on accesses to LEN bytes (default 1) at ADDR: r for reads, \
+                               w for writes (the default) and x for instruction fetches",
+                  "ADDR[:LEN][:rwx]");
+    opts.optopt("", "trace", "Log every memory access to FILE in vesta's binary trace format",
+                "FILE");
+    opts.optopt("", "l1i", "Simulate an L1 instruction cache", "SIZE:ASSOC:LINE[:lru|fifo|random]");
+    opts.optopt("", "l1d", "Simulate an L1 data cache", "SIZE:ASSOC:LINE[:lru|fifo|random]");
+    opts.optopt("", "l2", "Simulate a unified L2 cache", "SIZE:ASSOC:LINE[:lru|fifo|random]");
+    opts.optopt("", "unclaimed-ports", "What accesses to ports without a device do \
+                                        (default: open)", "open|fault");
+    opts.optopt("", "alignment", "What misaligned long accesses do (default: allow)",
+                "allow|fault|warn");
+    opts.optflag("", "serial", "Attach a UART at 0x3F8 to this terminal, putting it in raw mode");
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => { m }
-        Err(f) => { panic!(f.to_string()) }
+        Err(f) => { fatal!("{}", f) }
     };
 
     if matches.opt_present("D") {
-        TermLogger::init(LogLevelFilter::Debug, Config::default()).unwrap();
+        TermLogger::init(LevelFilter::Debug, Config::default(), TerminalMode::Mixed, ColorChoice::Auto)
+            .unwrap();
         debug!("Debugging is enabled!");
     } else {
-        let config = Config {
-            time: None,
-            level: None,
-            target: None,
-            location: None
-        };
+        let config = ConfigBuilder::new()
+            .set_max_level(LevelFilter::Off)
+            .set_time_level(LevelFilter::Off)
+            .set_target_level(LevelFilter::Off)
+            .set_location_level(LevelFilter::Off)
+            .build();
 
-        TermLogger::init(LogLevelFilter::Info, config).unwrap();
+        TermLogger::init(LevelFilter::Info, config, TerminalMode::Mixed, ColorChoice::Auto).unwrap();
     }
 
     if matches.opt_present("h") {
         print_usage(opts);
     }
 
-    //TODO: put this default somewhere nice.
-    let memory_size: u32 = matches.opt_str("M")
-                                  .map(|s| s.parse().unwrap_or_die(ERR_PARSE_MEM_SIZE))
-                                  .unwrap_or(DEFAULT_MEM_SIZE);
+    let memory_size: u64 = matches.opt_str("M")
+                                  .map(|s| parse_size(&s).unwrap_or_else(|| fatal!("{}", ERR_PARSE_MEM_SIZE)))
+                                  .unwrap_or(DEFAULT_MEM_SIZE as u64);
+
+    let limits = RunLimits {
+        max_instructions: matches.opt_str("max-instructions")
+                                 .map(|s| s.parse().unwrap_or_die(ERR_PARSE_MAX_INSTRUCTIONS)),
+        timeout: matches.opt_str("timeout")
+                        .map(|s| s.parse().unwrap_or_die(ERR_PARSE_TIMEOUT))
+                        .map(|secs| Duration::try_from_secs_f64(secs).unwrap_or_die(ERR_PARSE_TIMEOUT))
+    };
+
+    let triple_fault_action = match matches.opt_str("triple-fault").as_ref().map(|s| &s[..]) {
+        None | Some("shutdown") => TripleFaultAction::Shutdown,
+        Some("reset") => TripleFaultAction::Reset,
+        Some(_) => fatal!("{}", ERR_PARSE_TRIPLE_FAULT)
+    };
+
+    let defaults = PowerOnState::default();
+    let power_on = PowerOnState {
+        reset_vector: matches.opt_str("reset-vector")
+                             .map(|s| parse_number(&s).unwrap_or_die(ERR_PARSE_ADDRESS))
+                             .unwrap_or(defaults.reset_vector),
+        rflags: matches.opt_str("rflags")
+                       .map(|s| parse_number(&s).unwrap_or_die(ERR_PARSE_RFLAGS))
+                       .unwrap_or(defaults.rflags),
+        stack_pointer: matches.opt_str("stack")
+                              .map(|s| parse_number(&s).unwrap_or_die(ERR_PARSE_ADDRESS))
+                              .unwrap_or(defaults.stack_pointer),
+        clear_memory: matches.opt_present("clear-memory")
+    };
+
+    let tlb_size = matches.opt_str("tlb-size")
+                          .map(|s| s.parse().unwrap_or_die(ERR_PARSE_TLB_SIZE))
+                          .unwrap_or(DEFAULT_TLB_SIZE);
+
+    let alignment_policy = match matches.opt_str("alignment").as_ref().map(|s| &s[..]) {
+        None | Some("allow") => AlignmentPolicy::Allow,
+        Some("fault") => AlignmentPolicy::Fault,
+        Some("warn") => AlignmentPolicy::Warn,
+        Some(_) => fatal!("{}", ERR_PARSE_ALIGNMENT)
+    };
+
+    let unclaimed_ports = match matches.opt_str("unclaimed-ports").as_ref().map(|s| &s[..]) {
+        None | Some("open") => UnclaimedPorts::Open,
+        Some("fault") => UnclaimedPorts::Fault,
+        Some(_) => fatal!("{}", ERR_PARSE_UNCLAIMED_PORTS)
+    };
+
+    let rom_writes = match matches.opt_str("rom-writes").as_ref().map(|s| &s[..]) {
+        None | Some("fault") => RomWrites::Fault,
+        Some("ignore") => RomWrites::Ignore,
+        Some(_) => fatal!("{}", ERR_PARSE_ROM_WRITES)
+    };
+
+    if memory_size < MIN_MEM_SIZE as u64 {
+        fatal!("{}", VmError::MemoryTooSmall(memory_size));
+    }
+
+    let mut map = MemoryMap::new();
+    map.add_ram(0, memory_size).unwrap_or_else(|e| fatal!("{}", e));
+
+    for arg in matches.opt_strs("ram") {
+        let (base, size) = parse_region(&arg).unwrap_or_else(|| fatal!("{}", ERR_PARSE_REGION));
+        let size = parse_size(size).unwrap_or_else(|| fatal!("{}", ERR_PARSE_REGION));
+
+        map.add_ram(base, size).unwrap_or_else(|e| fatal!("{}", e));
+    }
+
+    for arg in matches.opt_strs("rom") {
+        let (base, path) = parse_region(&arg).unwrap_or_else(|| fatal!("{}", ERR_PARSE_REGION));
+        let contents = fs::read(path).unwrap_or_else(|e| fatal!("Cannot read ROM {}: {}", path, e));
+
+        map.add_rom(base, &contents, rom_writes).unwrap_or_else(|e| fatal!("{}", e));
+    }
+
+    if matches.opt_present("show-map") {
+        info!("{}", map);
+    }
+
+    let cache_opt = |name| matches.opt_str(name)
+                                  .map(|s| parse_cache(&s).unwrap_or_else(|| fatal!("{}", ERR_PARSE_CACHE)));
+    let (l1i, l1d, l2) = (cache_opt("l1i"), cache_opt("l1d"), cache_opt("l2"));
+
+    // Asking for any cache simulates the whole hierarchy.
+    let caches = if l1i.is_some() || l1d.is_some() || l2.is_some() {
+        let default_l1 = CacheConfig {
+            size: DEFAULT_L1_SIZE,
+            associativity: DEFAULT_L1_ASSOCIATIVITY,
+            line_size: DEFAULT_CACHE_LINE_SIZE,
+            policy: ReplacementPolicy::Lru
+        };
+
+        Some(CacheHierarchy::new(l1i.unwrap_or(default_l1), l1d.unwrap_or(default_l1), l2)
+                            .unwrap_or_else(|e| fatal!("{}", e)))
+    } else {
+        None
+    };
 
     let kernel_file = if matches.free.len() == 1 {
         &matches.free[0]
@@ -72,6 +307,74 @@ fn main() {
         print_usage(opts);
     };
 
-    let cpu = Cpu::new(kernel_file, memory_size);
-    cpu.boot();
+    let serial = matches.opt_present("serial");
+
+    let mut cpu = Cpu::with_bus(Box::new(map));
+    cpu.load_file(kernel_file, 0).unwrap_or_else(|e| fatal!("{}", e));
+    cpu.triple_fault_action = triple_fault_action;
+    cpu.alignment_policy = alignment_policy;
+    cpu.ports.unclaimed = unclaimed_ports;
+    cpu.ports.register(TIMER_PORT, TIMER_PORTS, Box::new(Timer::new()))
+             .unwrap_or_else(|e| fatal!("{}", e));
+
+    if serial {
+        let uart = Uart::new(Some(uart::stdin_reader()), Box::new(io::stdout()));
+        cpu.ports.register(UART_PORT, UART_PORTS, Box::new(uart))
+                 .unwrap_or_else(|e| fatal!("{}", e));
+    }
+
+    cpu.power_on = power_on;
+    cpu.tlb = Tlb::new(tlb_size);
+    cpu.caches = caches;
+    cpu.reset(ResetKind::Warm);
+
+    for arg in matches.opt_strs("watch") {
+        let watchpoint = parse_watchpoint(&arg).unwrap_or_else(|| fatal!("{}", ERR_PARSE_WATCHPOINT));
+        cpu.watchpoints.push(watchpoint);
+    }
+
+    if let Some(path) = matches.opt_str("trace") {
+        let trace = fs::File::create(&path).and_then(TraceWriter::new)
+                                           .unwrap_or_else(|e| fatal!("Cannot create trace {}: {}", path, e));
+        cpu.trace = Some(trace);
+    }
+
+    if serial {
+        term::enter_raw_mode();
+    }
+
+    let result = cpu.run_with_limits(limits);
+
+    if let Some(trace) = cpu.trace.take() {
+        if let Err(e) = trace.finish() {
+            error!("Cannot write trace: {}", e);
+        }
+    }
+
+    print_stats(&cpu);
+
+    match result {
+        Ok(StopReason::Halted(0)) => {
+            info!("Halt instruction reached with status 0.");
+            process::exit(0);
+        }
+        Ok(StopReason::Halted(status)) => {
+            error!("Halt instruction reached with status {}.\n{}", status, CrashReport::new(&cpu));
+            process::exit(halt_exit_status(status));
+        }
+        Ok(StopReason::InstructionLimit) => {
+            error!("Instruction limit reached, stopping.\n{}", CrashReport::new(&cpu));
+            process::exit(WATCHDOG_EXIT_STATUS);
+        }
+        Ok(StopReason::Timeout) => {
+            error!("Timed out, stopping.\n{}", CrashReport::new(&cpu));
+            process::exit(WATCHDOG_EXIT_STATUS);
+        }
+        Ok(StopReason::Watchpoint(hit)) => {
+            info!("{}\n{}", hit, CrashReport::new(&cpu));
+            process::exit(WATCHPOINT_EXIT_STATUS);
+        }
+        Err(e @ VmError::TripleFault(_)) => fatal!("{}\n{}", e, CrashReport::new(&cpu)),
+        Err(e) => fatal!("{}", e)
+    }
 }
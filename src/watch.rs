@@ -0,0 +1,63 @@
+use std::fmt;
+
+use mem::Access;
+
+/// Watches a range of guest virtual addresses for some kinds of access.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u32,
+    /// Number of bytes watched, from `addr`.
+    pub len: u32,
+    pub read: bool,
+    pub write: bool,
+    /// Instruction fetches.
+    pub execute: bool
+}
+
+impl Watchpoint {
+    /// Does a `width`-byte access at `addr` touch the watched range?
+    pub fn matches(&self, addr: u32, width: u32, access: Access) -> bool {
+        let kind = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Fetch => self.execute
+        };
+
+        kind && (addr as u64) < self.addr as u64 + self.len as u64
+             && (self.addr as u64) < addr as u64 + width as u64
+    }
+}
+
+/// A watched access made by a completed instruction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Address of the instruction which made the access.
+    pub rp: u32,
+    pub addr: u32,
+    /// Width of the access in bytes.
+    pub width: u32,
+    pub access: Access,
+    /// Value in memory before the access, or `None` if it could not be read
+    /// without side effects, as with most device registers.
+    pub old: Option<u32>,
+    /// Value in memory after the access. Only differs from `old` for writes.
+    pub new: u32
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Fetch => "fetch"
+        };
+
+        write!(f, "Watchpoint: {}-byte {} of 0x{:X} by instruction at 0x{:X}: ",
+               self.width, kind, self.addr, self.rp)?;
+
+        match self.old {
+            Some(old) => write!(f, "0x{:X} -> 0x{:X}", old, self.new),
+            None => write!(f, "? -> 0x{:X}", self.new)
+        }
+    }
+}
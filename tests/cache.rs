@@ -0,0 +1,93 @@
+extern crate vesta;
+
+use vesta::{Cache, CacheConfig, CacheHierarchy, ReplacementPolicy, VmError};
+
+const LINE: u32 = 32;
+
+/// A cache with a single 2-way set, so every line competes for it.
+fn one_set(policy: ReplacementPolicy) -> Cache {
+    Cache::new("test", CacheConfig { size: LINE * 2, associativity: 2, line_size: LINE, policy }).unwrap()
+}
+
+/// Runs `addrs` through `cache`, returning which of them hit.
+fn run(cache: &mut Cache, addrs: &[u32]) -> Vec<bool> {
+    addrs.iter().map(|&a| cache.access(a)).collect()
+}
+
+const A: u32 = 0;
+const B: u32 = LINE;
+const C: u32 = LINE * 2;
+
+#[test]
+fn lru_evicts_least_recently_used_line() {
+    let mut cache = one_set(ReplacementPolicy::Lru);
+
+    // C replaces B, since A was used more recently, so B then misses and
+    // replaces C.
+    assert_eq!(run(&mut cache, &[A, B, A, C, A, B, A]),
+               [false, false, true, false, true, false, true]);
+}
+
+#[test]
+fn fifo_evicts_oldest_filled_line() {
+    let mut cache = one_set(ReplacementPolicy::Fifo);
+
+    // Hitting A does not save it: C replaces it regardless, then A
+    // replaces B.
+    assert_eq!(run(&mut cache, &[A, B, A, C, B, A, C, B]),
+               [false, false, true, false, true, false, true, false]);
+}
+
+#[test]
+fn counters_add_up() {
+    let mut cache = one_set(ReplacementPolicy::Lru);
+    run(&mut cache, &[A, A + 4, A + LINE - 1, B, C, C + 8]);
+
+    assert_eq!(cache.hits, 3);
+    assert_eq!(cache.misses, 3);
+    assert_eq!(cache.evictions, 1);
+}
+
+#[test]
+fn random_policy_is_repeatable() {
+    let addrs: Vec<u32> = (0..64).map(|i| (i * 7 % 5) * LINE).collect();
+    let mut first = one_set(ReplacementPolicy::Random);
+    let mut second = one_set(ReplacementPolicy::Random);
+
+    assert_eq!(run(&mut first, &addrs), run(&mut second, &addrs));
+    assert_eq!(first.hits + first.misses, 64);
+    assert_eq!(first.evictions, first.misses - 2);
+}
+
+#[test]
+fn bad_geometry_is_rejected() {
+    let bad = [(96, 2, LINE), (64, 3, LINE), (64, 2, 24), (64, 4, LINE)];
+
+    for &(size, associativity, line_size) in &bad {
+        let config = CacheConfig { size, associativity, line_size, policy: ReplacementPolicy::Lru };
+
+        match Cache::new("bad", config) {
+            Err(VmError::InvalidCache { .. }) => {}
+            Err(e) => panic!("wrong error: {}", e),
+            Ok(_) => panic!("{}/{}/{} was accepted", size, associativity, line_size)
+        }
+    }
+}
+
+#[test]
+fn l1_misses_go_to_l2() {
+    let l1 = CacheConfig { size: LINE * 2, associativity: 2, line_size: LINE, policy: ReplacementPolicy::Lru };
+    let l2 = CacheConfig { size: LINE * 16, ..l1 };
+    let mut caches = CacheHierarchy::new(l1, l1, Some(l2)).unwrap();
+
+    // A long straddling two lines touches both.
+    caches.access(B - 2, 4, false);
+    caches.access(C, 1, true);
+    caches.access(A, 1, false);
+    caches.access(B, 4, false);
+
+    let l2 = caches.l2.as_ref().unwrap();
+    assert_eq!((caches.l1d.hits, caches.l1d.misses), (2, 2));
+    assert_eq!((caches.l1i.hits, caches.l1i.misses), (0, 1));
+    assert_eq!((l2.hits, l2.misses), (0, 3));
+}
@@ -0,0 +1,100 @@
+extern crate vesta;
+
+use vesta::{Cpu, Flag, Mem, StepOutcome, TripleFaultAction, VmError};
+use vesta::flag::{EXTERNAL_FLAG, PROTECT_FLAG};
+use vesta::interrupt::{DOUBLE_FAULT_INTERRUPT, PROTECT_INTERRUPT};
+use vesta::mmu::*;
+use vesta::pic::{PIC_MASK, PIC_PORT};
+use vesta::reset::ResetKind;
+
+const MEMORY: u32 = 0x1000;
+const CODE: u32 = 0x100;
+const HANDLER: u32 = 0x200;
+const STACK: u32 = 0x800;
+
+/// INT 0x40
+const SOFTWARE_INTERRUPT: [u8; 2] = [0x8E, 0x40];
+const VECTOR: u8 = 0x40;
+const RETURN: u32 = CODE + SOFTWARE_INTERRUPT.len() as u32;
+
+/// A CPU about to raise `VECTOR`, with its vector table at `ri` so that
+/// `VECTOR`'s entry lies past the end of memory.
+fn cpu_with_vectors(ri: u32) -> Cpu {
+    let mut cpu = Cpu::with_memory(MEMORY as u64);
+    cpu.load_image(&SOFTWARE_INTERRUPT, CODE).unwrap();
+
+    let double_fault = ri + DOUBLE_FAULT_INTERRUPT as u32 * 4;
+    if double_fault < MEMORY {
+        cpu.mem_set_long(double_fault, HANDLER);
+    }
+
+    cpu.ri = ri;
+    cpu.rp = CODE;
+    cpu.reg[15] = STACK;
+    cpu.flag_set(EXTERNAL_FLAG, true);
+    cpu
+}
+
+#[test]
+fn faulting_delivery_enters_double_fault() {
+    let mut cpu = cpu_with_vectors(MEMORY - 0x100);
+
+    assert_eq!(cpu.step().unwrap(), StepOutcome::DoubleFault(VECTOR));
+    assert_eq!(cpu.rp, HANDLER);
+
+    // Only DOUBLE_FAULT's frame is on the stack, with the original vector
+    // as its code.
+    assert_eq!(cpu.reg[15], STACK - 12);
+    assert_eq!(cpu.mem_peek_long(STACK - 12), Some(VECTOR as u32));
+    assert_eq!(cpu.mem_peek_long(STACK - 8), Some(RETURN));
+    assert!(cpu.mem_peek_long(STACK - 4).unwrap() & EXTERNAL_FLAG != 0);
+}
+
+#[test]
+fn triple_fault_can_reset() {
+    let mut cpu = cpu_with_vectors(MEMORY - 0x10);
+    cpu.triple_fault_action = TripleFaultAction::Reset;
+
+    assert_eq!(cpu.step().unwrap(), StepOutcome::Reset(ResetKind::Warm));
+    assert_eq!(cpu.rp, cpu.power_on.reset_vector);
+    assert_eq!(cpu.reg[15], cpu.power_on.stack_pointer);
+}
+
+#[test]
+fn triple_fault_shutdown_keeps_state_at_the_fault() {
+    let mut cpu = cpu_with_vectors(MEMORY - 0x10);
+
+    match cpu.step() {
+        Err(VmError::TripleFault(VECTOR)) => {}
+        other => panic!("expected a triple fault, got {:?}", other)
+    }
+
+    assert_eq!(cpu.rp, RETURN);
+    assert_eq!(cpu.reg[15], STACK);
+    assert!(cpu.flag_get(EXTERNAL_FLAG));
+}
+
+#[test]
+fn port_io_in_user_mode_raises_protect() {
+    let mut cpu = Cpu::with_memory(MEMORY as u64 * 3);
+    let port = (PIC_PORT + PIC_MASK).to_le_bytes();
+
+    // Identity map the first page for user mode.
+    let (directory, table) = (MEMORY, MEMORY * 2);
+    let rwxu = PTE_PRESENT | PTE_READ | PTE_WRITE | PTE_EXECUTE | PTE_USER;
+    cpu.mem_set_long(directory, table | rwxu);
+    cpu.mem_set_long(table, rwxu);
+    cpu.rm = directory;
+
+    // OUTS const PIC_PORT + PIC_MASK, r1
+    cpu.load_image(&[0xA7, 0x0C, port[0], port[1], port[2], port[3], 0x12], CODE).unwrap();
+    cpu.mem_set_long(PROTECT_INTERRUPT as u32 * 4, HANDLER);
+    cpu.rp = CODE;
+    cpu.rks = STACK;
+    cpu.reg[1] = 0xFF;
+    cpu.flag_set(PROTECT_FLAG, true);
+
+    assert_eq!(cpu.step().unwrap(), StepOutcome::Interrupt(PROTECT_INTERRUPT));
+    assert_eq!(cpu.rp, HANDLER);
+    assert_eq!(cpu.ports.read_short(PIC_PORT + PIC_MASK), Some(0));
+}
@@ -0,0 +1,101 @@
+extern crate vesta;
+
+use vesta::{Bus, Cpu, Mem, MemoryMap, RomWrites, StepOutcome, VmError};
+use vesta::interrupt::MEMORY_INTERRUPT;
+
+const ROM: u32 = 0x1000;
+const RAM: u32 = 0x2000;
+
+/// 4 KiB of RAM at `RAM`, with ROM holding 0..16 right below it.
+fn map(writes: RomWrites) -> MemoryMap {
+    let contents: Vec<u8> = (0..16).collect();
+    let mut map = MemoryMap::new();
+    map.add_ram(RAM, 0x1000).unwrap();
+    map.add_rom(RAM - contents.len() as u32, &contents, writes).unwrap();
+    map.add_rom(ROM, &contents, writes).unwrap();
+    map
+}
+
+#[test]
+fn rom_writes_fault_or_are_ignored() {
+    let mut faulting = map(RomWrites::Fault);
+    assert!(!faulting.write_short(ROM, 0xFF));
+    assert!(!faulting.write_long(ROM + 4, 0xFFFF_FFFF));
+    assert_eq!(faulting.read_short(ROM), Some(0));
+
+    let mut ignoring = map(RomWrites::Ignore);
+    assert!(ignoring.write_short(ROM, 0xFF));
+    assert!(ignoring.write_long(ROM + 4, 0xFFFF_FFFF));
+    assert_eq!(ignoring.read_long(ROM + 4), Some(0x0706_0504));
+}
+
+#[test]
+fn rom_write_raises_memory_interrupt() {
+    let mut cpu = Cpu::with_bus(Box::new(map(RomWrites::Fault)));
+
+    // MOV r2, [r1]
+    cpu.load_image(&[0x30, 0x0A, 0x11], RAM).unwrap();
+    cpu.mem_set_long(RAM + 0x800 + MEMORY_INTERRUPT as u32 * 4, RAM + 0x100);
+    cpu.ri = RAM + 0x800;
+    cpu.rp = RAM;
+    cpu.reg[1] = ROM;
+    cpu.reg[15] = RAM + 0x1000;
+
+    assert_eq!(cpu.step().unwrap(), StepOutcome::Interrupt(MEMORY_INTERRUPT));
+    assert_eq!(cpu.rp, RAM + 0x100);
+    assert_eq!(cpu.rf, ROM);
+    assert_eq!(cpu.mem_peek_long(ROM), Some(0x0302_0100));
+}
+
+#[test]
+fn overlapping_regions_are_rejected() {
+    let mut map = map(RomWrites::Fault);
+
+    for &(base, size) in &[(RAM, 1), (RAM - 1, 2), (RAM + 0xFFF, 0x10), (0, 0x1001), (0, 0x10_0000)] {
+        match map.add_ram(base, size) {
+            Err(VmError::InvalidRegion { .. }) => {}
+            other => panic!("0x{:X}+0x{:X} was not rejected: {:?}", base, size, other)
+        }
+    }
+
+    // Right up against existing regions is fine.
+    map.add_ram(RAM + 0x1000, 0x10).unwrap();
+    map.add_ram(0, ROM as u64).unwrap();
+}
+
+#[test]
+fn accesses_span_adjacent_regions() {
+    let mut map = map(RomWrites::Ignore);
+    let last = RAM - 1;
+
+    map.write_short(RAM, 0xAA);
+    assert_eq!(map.read_long(last - 2), Some(0xAA0F_0E0D));
+    assert_eq!(map.peek_long(last - 2), Some(0xAA0F_0E0D));
+
+    // The ROM bytes are dropped while the RAM ones land.
+    assert!(map.write_long(last, 0x4433_2211));
+    assert_eq!(map.read_long(last), Some(0x4433_220F));
+}
+
+#[test]
+fn spanning_writes_touching_unmapped_memory_change_nothing() {
+    let mut map = map(RomWrites::Fault);
+    let end = RAM + 0x1000;
+
+    assert!(!map.write_long(end - 2, 0xFFFF_FFFF));
+    assert_eq!(map.read_short(end - 2), Some(0));
+    assert_eq!(map.read_long(end - 2), None);
+}
+
+#[test]
+fn images_load_across_regions_but_not_into_gaps() {
+    let mut map = map(RomWrites::Fault);
+    let image = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    assert!(map.load(RAM - 4, &image));
+    assert_eq!(map.read_long(RAM - 4), Some(0x0403_0201));
+    assert_eq!(map.read_long(RAM), Some(0x0807_0605));
+
+    assert!(!map.load(ROM + 12, &image));
+    assert_eq!(map.read_long(ROM + 12), Some(0x0F0E_0D0C));
+}
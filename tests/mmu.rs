@@ -0,0 +1,236 @@
+extern crate vesta;
+
+use vesta::{Cpu, Mem, Flag, Reset, ResetKind, StepOutcome};
+use vesta::flag::*;
+use vesta::interrupt::MEMORY_INTERRUPT;
+use vesta::mmu::*;
+
+const DIRECTORY: u32 = 0x1000;
+const CODE: u32 = 0x3000;
+const VECTORS: u32 = 0x4000;
+const HANDLER: u32 = 0x4800;
+const USER_STACK: u32 = 0x5800;
+const KERNEL_STACK: u32 = 0x6000;
+const DATA: u32 = 0x7000;
+/// Page table `n` maps the `n`th 4 MiB of the address space.
+const TABLES: u32 = 0x10000;
+
+/// A page mapped at a virtual address far from its physical frame.
+const DATA_VA: u32 = 0x0040_0000;
+const UNMAPPED_VA: u32 = 0x0080_0000;
+
+const RWXU: u32 = PTE_READ | PTE_WRITE | PTE_EXECUTE | PTE_USER;
+
+/// Maps the page of `va` to `pa` with `permissions`.
+fn map(cpu: &mut Cpu, va: u32, pa: u32, permissions: u32) {
+    let dir_index = va >> 22;
+    let table = TABLES + dir_index * PAGE_SIZE;
+    cpu.mem_set_long(DIRECTORY + dir_index * 4, table | PTE_PRESENT | RWXU);
+
+    let table_index = (va >> PAGE_SHIFT) & (ENTRIES_PER_TABLE - 1);
+    cpu.mem_set_long(table + table_index * 4, pa | PTE_PRESENT | permissions);
+}
+
+/// A CPU with the low 32 KiB identity mapped for user mode, `DATA_VA`
+/// mapped to `DATA`, and a MEMORY handler at `HANDLER`, still in kernel
+/// mode.
+fn paged_cpu() -> Cpu {
+    let mut cpu = Cpu::with_memory(0x20000);
+
+    for page in 0..8 {
+        map(&mut cpu, page * PAGE_SIZE, page * PAGE_SIZE, RWXU);
+    }
+
+    map(&mut cpu, DATA_VA, DATA, PTE_READ | PTE_WRITE | PTE_USER);
+    cpu.mem_set_long(VECTORS + MEMORY_INTERRUPT as u32 * 4, HANDLER);
+
+    cpu.rm = DIRECTORY;
+    cpu.ri = VECTORS;
+    cpu.rks = KERNEL_STACK;
+    cpu.reg[15] = USER_STACK;
+    cpu
+}
+
+/// Runs `code` at `CODE` in user mode for one instruction.
+fn step_user(cpu: &mut Cpu, code: &[u8]) -> StepOutcome {
+    cpu.load_image(code, CODE).unwrap();
+    cpu.rp = CODE;
+    cpu.flag_set(PROTECT_FLAG, true);
+    cpu.step().unwrap()
+}
+
+/// MOV [r1], r2
+const LOAD_R1_TO_R2: [u8; 3] = [0x30, 0x11, 0x0A];
+
+#[test]
+fn user_accesses_are_translated() {
+    let mut cpu = paged_cpu();
+    cpu.mem_set_long(DATA + 0x10, 0xDEAD_BEEF);
+    cpu.reg[1] = DATA_VA + 0x10;
+
+    assert_eq!(step_user(&mut cpu, &LOAD_R1_TO_R2), StepOutcome::Executed);
+    assert_eq!(cpu.reg[2], 0xDEAD_BEEF);
+}
+
+#[test]
+fn kernel_accesses_are_translated_only_with_kernel_paging() {
+    let mut cpu = paged_cpu();
+    cpu.mem_set_long(DATA, 1);
+
+    assert_eq!(cpu.mem_get_long(DATA_VA), 0);
+
+    cpu.flag_set(KERNEL_PAGING_FLAG, true);
+    assert_eq!(cpu.mem_get_long(DATA_VA), 1);
+}
+
+#[test]
+fn unmapped_access_raises_memory_interrupt() {
+    let mut cpu = paged_cpu();
+    cpu.reg[1] = UNMAPPED_VA + 0x24;
+
+    assert_eq!(step_user(&mut cpu, &LOAD_R1_TO_R2), StepOutcome::Interrupt(MEMORY_INTERRUPT));
+    assert_eq!(cpu.rf, UNMAPPED_VA + 0x24);
+    assert_eq!(cpu.rp, HANDLER);
+    assert!(!cpu.flag_get(PROTECT_FLAG));
+}
+
+/// MOV r2, [r1]
+const STORE_R2_TO_R1: [u8; 3] = [0x30, 0x0A, 0x11];
+
+/// The error code pushed by the last fault, after the user stack pointer,
+/// rflags and return address.
+fn fault_code(cpu: &Cpu) -> u32 {
+    assert_eq!(cpu.reg[15], KERNEL_STACK - 16);
+    cpu.mem_peek_long(KERNEL_STACK - 16).unwrap()
+}
+
+#[test]
+fn fault_codes_say_which_check_failed() {
+    let mut cpu = paged_cpu();
+    cpu.reg[1] = UNMAPPED_VA;
+    step_user(&mut cpu, &LOAD_R1_TO_R2);
+    assert_eq!(fault_code(&cpu), FAULT_NOT_PRESENT);
+
+    let mut cpu = paged_cpu();
+    map(&mut cpu, DATA_VA, DATA, PTE_READ | PTE_USER);
+    cpu.reg[1] = DATA_VA + 8;
+    assert_eq!(step_user(&mut cpu, &STORE_R2_TO_R1), StepOutcome::Interrupt(MEMORY_INTERRUPT));
+    assert_eq!(fault_code(&cpu), FAULT_NO_WRITE);
+    assert_eq!(cpu.rf, DATA_VA + 8);
+
+    let mut cpu = paged_cpu();
+    map(&mut cpu, DATA_VA, DATA, PTE_WRITE | PTE_USER);
+    cpu.reg[1] = DATA_VA;
+    step_user(&mut cpu, &LOAD_R1_TO_R2);
+    assert_eq!(fault_code(&cpu), FAULT_NO_READ);
+
+    let mut cpu = paged_cpu();
+    map(&mut cpu, DATA_VA, DATA, PTE_READ | PTE_WRITE);
+    cpu.reg[1] = DATA_VA;
+    step_user(&mut cpu, &LOAD_R1_TO_R2);
+    assert_eq!(fault_code(&cpu), FAULT_SUPERVISOR);
+}
+
+#[test]
+fn fetch_from_no_execute_page_faults() {
+    let mut cpu = paged_cpu();
+    cpu.mem_set_long(DATA, 0x8D);
+    cpu.rp = DATA_VA;
+    cpu.flag_set(PROTECT_FLAG, true);
+
+    assert_eq!(cpu.step().unwrap(), StepOutcome::Interrupt(MEMORY_INTERRUPT));
+    assert_eq!(fault_code(&cpu), FAULT_NO_EXECUTE);
+    assert_eq!(cpu.rf, DATA_VA);
+}
+
+#[test]
+fn faulting_store_leaves_memory_untouched() {
+    let mut cpu = paged_cpu();
+    map(&mut cpu, DATA_VA, DATA, PTE_READ | PTE_USER);
+    cpu.mem_set_long(DATA, 5);
+    cpu.reg[1] = DATA_VA;
+    cpu.reg[2] = 6;
+    step_user(&mut cpu, &STORE_R2_TO_R1);
+
+    assert_eq!(cpu.mem_peek_long(DATA), Some(5));
+}
+
+/// The directory and table entries mapping `va`, whatever the CPU mode.
+fn entries(cpu: &Cpu, va: u32) -> (u32, u32) {
+    let dir_index = va >> 22;
+    let table_index = (va >> PAGE_SHIFT) & (ENTRIES_PER_TABLE - 1);
+    let table = TABLES + dir_index * PAGE_SIZE;
+
+    (cpu.bus.peek_long(DIRECTORY + dir_index * 4).unwrap(),
+     cpu.bus.peek_long(table + table_index * 4).unwrap())
+}
+
+#[test]
+fn reads_set_accessed_bits() {
+    let mut cpu = paged_cpu();
+    cpu.reg[1] = DATA_VA;
+    step_user(&mut cpu, &LOAD_R1_TO_R2);
+
+    let (pde, pte) = entries(&cpu, DATA_VA);
+    assert!(pde & PTE_ACCESSED != 0);
+    assert!(pte & PTE_ACCESSED != 0);
+    assert!(pte & PTE_DIRTY == 0);
+}
+
+#[test]
+fn writes_set_accessed_and_dirty_bits() {
+    let mut cpu = paged_cpu();
+    cpu.reg[1] = DATA_VA;
+    step_user(&mut cpu, &STORE_R2_TO_R1);
+
+    let (pde, pte) = entries(&cpu, DATA_VA);
+    assert!(pde & PTE_ACCESSED != 0);
+    assert_eq!(pte & (PTE_ACCESSED | PTE_DIRTY), PTE_ACCESSED | PTE_DIRTY);
+}
+
+#[test]
+fn faulting_write_leaves_entries_untouched() {
+    let mut cpu = paged_cpu();
+    map(&mut cpu, DATA_VA, DATA, PTE_READ | PTE_USER);
+    let before = entries(&cpu, DATA_VA);
+    cpu.reg[1] = DATA_VA;
+    step_user(&mut cpu, &STORE_R2_TO_R1);
+
+    assert_eq!(entries(&cpu, DATA_VA), before);
+}
+
+/// LOM r3
+const LOAD_RM_FROM_R3: [u8; 2] = [0x70, 0x0E];
+
+#[test]
+fn translations_are_stale_until_lom() {
+    let mut cpu = paged_cpu();
+    cpu.mem_set_long(DATA, 1);
+    cpu.mem_set_long(DATA + PAGE_SIZE, 2);
+    cpu.reg[1] = DATA_VA;
+    step_user(&mut cpu, &LOAD_R1_TO_R2);
+    assert_eq!(cpu.reg[2], 1);
+
+    cpu.flag_set(PROTECT_FLAG, false);
+    map(&mut cpu, DATA_VA, DATA + PAGE_SIZE, PTE_READ | PTE_WRITE | PTE_USER);
+    step_user(&mut cpu, &LOAD_R1_TO_R2);
+    assert_eq!(cpu.reg[2], 1);
+
+    cpu.flag_set(PROTECT_FLAG, false);
+    cpu.load_image(&LOAD_RM_FROM_R3, CODE).unwrap();
+    cpu.rp = CODE;
+    cpu.reg[3] = DIRECTORY;
+    assert_eq!(cpu.step().unwrap(), StepOutcome::Executed);
+    assert_eq!(cpu.tlb.flushes, 1);
+
+    step_user(&mut cpu, &LOAD_R1_TO_R2);
+    assert_eq!(cpu.reg[2], 2);
+}
+
+#[test]
+fn reset_does_not_count_as_a_flush() {
+    let mut cpu = paged_cpu();
+    cpu.reset(ResetKind::Warm);
+
+    assert_eq!(cpu.tlb.flushes, 0);
+}
@@ -0,0 +1,175 @@
+extern crate vesta;
+
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+use std::sync::mpsc;
+
+use vesta::{Cpu, Flag, Mem, Pic, PortDevice, Reset, ResetKind, StepOutcome, Timer, Uart};
+use vesta::default::{DEFAULT_PIC_VECTOR_BASE, DEFAULT_UART_LINE};
+use vesta::flag::EXTERNAL_FLAG;
+use vesta::pic::*;
+use vesta::timer::*;
+use vesta::uart::*;
+
+const LOOP: u32 = 0x100;
+const VECTORS: u32 = 0x400;
+const DEVICE_PORT: u32 = 0x60;
+
+/// Asserts whichever lines are set in its cell on every tick.
+struct Lines(Rc<Cell<u8>>);
+
+impl PortDevice for Lines {
+    fn read_short(&mut self, _port: u32) -> u8 { 0 }
+    fn write_short(&mut self, _port: u32, _val: u8) {}
+
+    fn tick(&mut self, pic: &mut Pic) {
+        for line in 0..PIC_LINES {
+            if self.0.get() & (1 << line) != 0 {
+                pic.assert(line);
+            }
+        }
+    }
+}
+
+/// A CPU spinning on `JMP LOOP`, which every interrupt vector also points
+/// to, with a device driving the lines in the returned cell.
+fn cpu_with_lines() -> (Cpu, Rc<Cell<u8>>) {
+    let mut cpu = Cpu::with_memory(0x1000);
+    let lines = Rc::new(Cell::new(0));
+    cpu.ports.register(DEVICE_PORT, 1, Box::new(Lines(lines.clone()))).unwrap();
+
+    let target = LOOP.to_le_bytes();
+    cpu.load_image(&[0x80, 0x0C, target[0], target[1], target[2], target[3]], LOOP).unwrap();
+    for vector in 0..256 {
+        cpu.mem_set_long(VECTORS + vector * 4, LOOP);
+    }
+
+    cpu.ri = VECTORS;
+    cpu.rp = LOOP;
+    cpu.reg[15] = 0x1000;
+    (cpu, lines)
+}
+
+/// Steps with EXTERNAL set, so requests can be delivered.
+fn step(cpu: &mut Cpu) -> StepOutcome {
+    cpu.flag_set(EXTERNAL_FLAG, true);
+    cpu.step().unwrap()
+}
+
+/// Raises an edge on `line` for one instruction.
+fn pulse(cpu: &mut Cpu, lines: &Cell<u8>, line: u8) -> StepOutcome {
+    lines.set(1 << line);
+    let outcome = step(cpu);
+    lines.set(0);
+    outcome
+}
+
+fn vector(line: u8) -> StepOutcome {
+    StepOutcome::Interrupt(DEFAULT_PIC_VECTOR_BASE + line)
+}
+
+#[test]
+fn masked_lines_stay_requested() {
+    let (mut cpu, lines) = cpu_with_lines();
+    assert!(cpu.ports.write_short(PIC_PORT + PIC_MASK, 0b10));
+
+    assert_eq!(pulse(&mut cpu, &lines, 1), StepOutcome::Executed);
+    assert_eq!(step(&mut cpu), StepOutcome::Executed);
+    assert_eq!(cpu.ports.read_short(PIC_PORT + PIC_REQUESTED), Some(0b10));
+
+    cpu.ports.write_short(PIC_PORT + PIC_MASK, 0);
+    assert_eq!(step(&mut cpu), vector(1));
+}
+
+#[test]
+fn in_service_line_waits_for_eoi() {
+    let (mut cpu, lines) = cpu_with_lines();
+
+    assert_eq!(pulse(&mut cpu, &lines, 1), vector(1));
+    assert_eq!(cpu.ports.pic.in_service, 0b10);
+    assert_eq!(step(&mut cpu), StepOutcome::Executed);
+
+    assert_eq!(pulse(&mut cpu, &lines, 1), StepOutcome::Executed);
+    assert_eq!(step(&mut cpu), StepOutcome::Executed);
+
+    cpu.ports.write_short(PIC_PORT + PIC_COMMAND, PIC_EOI);
+    assert_eq!(cpu.ports.pic.in_service, 0);
+    assert_eq!(step(&mut cpu), vector(1));
+}
+
+#[test]
+fn better_priority_preempts_in_service_line() {
+    let (mut cpu, lines) = cpu_with_lines();
+
+    assert_eq!(pulse(&mut cpu, &lines, 3), vector(3));
+    assert_eq!(pulse(&mut cpu, &lines, 5), StepOutcome::Executed);
+    assert_eq!(pulse(&mut cpu, &lines, 0), vector(0));
+    assert_eq!(cpu.ports.pic.in_service, 0b1001);
+
+    // EOI ends the best in-service line first.
+    cpu.ports.write_short(PIC_PORT + PIC_COMMAND, PIC_EOI);
+    assert_eq!(cpu.ports.pic.in_service, 0b1000);
+}
+
+#[test]
+fn level_triggered_line_requests_while_high() {
+    let (mut cpu, lines) = cpu_with_lines();
+    cpu.ports.write_short(PIC_PORT + PIC_TRIGGER, 0b100);
+    lines.set(0b100);
+
+    assert_eq!(step(&mut cpu), vector(2));
+    cpu.ports.write_short(PIC_PORT + PIC_COMMAND, PIC_EOI);
+    assert_eq!(step(&mut cpu), vector(2));
+
+    lines.set(0);
+    cpu.ports.write_short(PIC_PORT + PIC_COMMAND, PIC_EOI);
+    assert_eq!(step(&mut cpu), StepOutcome::Executed);
+    assert_eq!(step(&mut cpu), StepOutcome::Executed);
+}
+
+#[test]
+fn reset_disarms_the_timer() {
+    let (mut cpu, _) = cpu_with_lines();
+    cpu.ports.register(TIMER_PORT, TIMER_PORTS, Box::new(Timer::new())).unwrap();
+    cpu.ports.write_long(TIMER_PORT + TIMER_RELOAD, 2);
+    cpu.ports.write_long(TIMER_PORT + TIMER_CONTROL, (TIMER_ENABLE | TIMER_PERIODIC) as u32);
+
+    cpu.reset(ResetKind::Warm);
+    cpu.rp = LOOP;
+
+    for _ in 0..8 {
+        assert_eq!(step(&mut cpu), StepOutcome::Executed);
+    }
+}
+
+#[test]
+fn spare_timer_ports_are_inert() {
+    let (mut cpu, _) = cpu_with_lines();
+    cpu.ports.register(TIMER_PORT, TIMER_PORTS + 1, Box::new(Timer::new())).unwrap();
+
+    assert!(cpu.ports.write_long(TIMER_PORT + TIMER_PORTS, 0xFFFF_FFFF));
+    assert_eq!(cpu.ports.read_long(TIMER_PORT + TIMER_PORTS), Some(0));
+    assert_eq!(cpu.ports.read_long(TIMER_PORT + TIMER_CONTROL), Some(0));
+}
+
+#[test]
+fn uart_requests_until_input_is_drained() {
+    let (mut cpu, _) = cpu_with_lines();
+    let (input, rx) = mpsc::channel();
+    cpu.ports.register(UART_PORT, UART_PORTS, Box::new(Uart::new(Some(rx), Box::new(io::sink())))).unwrap();
+    cpu.ports.write_short(UART_PORT + UART_INTERRUPT_ENABLE, UART_RX_INTERRUPT);
+
+    input.send(b'a').unwrap();
+    input.send(b'b').unwrap();
+    assert_eq!(step(&mut cpu), vector(DEFAULT_UART_LINE));
+
+    // The line stays high while a byte is left, so it requests again.
+    assert_eq!(cpu.ports.read_short(UART_PORT + UART_DATA), Some(b'a'));
+    cpu.ports.write_short(PIC_PORT + PIC_COMMAND, PIC_EOI);
+    assert_eq!(step(&mut cpu), vector(DEFAULT_UART_LINE));
+
+    assert_eq!(cpu.ports.read_short(UART_PORT + UART_DATA), Some(b'b'));
+    cpu.ports.write_short(PIC_PORT + PIC_COMMAND, PIC_EOI);
+    assert_eq!(step(&mut cpu), StepOutcome::Executed);
+}
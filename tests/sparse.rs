@@ -0,0 +1,70 @@
+extern crate vesta;
+
+use vesta::{Bus, SparseRam};
+
+const PAGE: u32 = 4096;
+const FOUR_GIB: u64 = 1 << 32;
+
+#[test]
+fn only_writes_allocate_pages() {
+    let mut ram = SparseRam::new(FOUR_GIB);
+
+    assert_eq!(ram.read_long(0x1234_5678), Some(0));
+    assert_eq!(ram.read_short(0xFFFF_FFFF), Some(0));
+    assert_eq!(ram.peek_long(PAGE * 3), Some(0));
+    assert!(ram.accepts(0x8000_0000, true));
+    assert_eq!(ram.allocated_pages(), 0);
+
+    assert!(ram.write_short(PAGE * 5 + 7, 1));
+    assert!(ram.write_long(PAGE * 5 + 8, 2));
+    assert_eq!(ram.allocated_pages(), 1);
+}
+
+#[test]
+fn unwritten_bytes_read_as_zero() {
+    let mut ram = SparseRam::new(FOUR_GIB);
+    ram.write_long(0xC000_0000, 0xDEAD_BEEF);
+
+    assert_eq!(ram.read_long(0xC000_0000), Some(0xDEAD_BEEF));
+    assert_eq!(ram.read_long(0xC000_0004), Some(0));
+    assert_eq!(ram.read_long(0xC000_0000 - 4), Some(0));
+    assert_eq!(ram.allocated_pages(), 1);
+}
+
+#[test]
+fn longs_and_images_cross_page_boundaries() {
+    let mut ram = SparseRam::new(FOUR_GIB);
+
+    assert!(ram.write_long(PAGE - 2, 0x4433_2211));
+    assert_eq!(ram.read_long(PAGE - 2), Some(0x4433_2211));
+    assert_eq!(ram.allocated_pages(), 2);
+
+    let image: Vec<u8> = (0..PAGE + 2).map(|i| i as u8).collect();
+    assert!(ram.load(PAGE * 9 - 1, &image));
+    assert_eq!(ram.read_short(PAGE * 9 - 1), Some(0));
+    assert_eq!(ram.read_short(PAGE * 10), Some(1));
+    assert_eq!(ram.allocated_pages(), 5);
+}
+
+#[test]
+fn accesses_past_the_end_fail() {
+    let mut ram = SparseRam::new(PAGE as u64 * 2);
+
+    assert_eq!(ram.read_short(PAGE * 2), None);
+    assert_eq!(ram.read_long(PAGE * 2 - 2), None);
+    assert!(!ram.write_short(PAGE * 2, 1));
+    assert!(!ram.write_long(PAGE * 2 - 2, 1));
+    assert!(!ram.load(PAGE * 2 - 1, &[1, 2]));
+    assert_eq!(ram.allocated_pages(), 0);
+}
+
+#[test]
+fn clear_frees_every_page() {
+    let mut ram = SparseRam::new(FOUR_GIB);
+    ram.write_short(0, 1);
+    ram.write_short(0xFFFF_FFFF, 1);
+    ram.clear();
+
+    assert_eq!(ram.allocated_pages(), 0);
+    assert_eq!(ram.read_short(0xFFFF_FFFF), Some(0));
+}
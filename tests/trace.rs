@@ -0,0 +1,86 @@
+extern crate vesta;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use vesta::{Cpu, TraceKind, TraceReader, TraceRecord, TraceWriter};
+
+/// A sink which can still be read after the writer owning it is gone.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn read_back(bytes: &[u8]) -> Vec<TraceRecord> {
+    TraceReader::new(bytes).unwrap().map(|r| r.unwrap()).collect()
+}
+
+#[test]
+fn records_round_trip() {
+    let kinds = [TraceKind::Fetch, TraceKind::Read, TraceKind::Write, TraceKind::Push, TraceKind::Pop];
+    let records: Vec<TraceRecord> = kinds.iter().enumerate().map(|(i, &kind)| TraceRecord {
+        instruction: (1 << 40) + i as u64,
+        va: 0xFFFF_FFF0 + i as u32,
+        pa: 0x1000 * i as u32,
+        width: if i % 2 == 0 { 1 } else { 4 },
+        kind
+    }).collect();
+
+    let sink = Shared::default();
+    let mut writer = TraceWriter::new(sink.clone()).unwrap();
+    for record in &records {
+        writer.record(record).unwrap();
+    }
+    writer.finish().unwrap();
+
+    assert_eq!(read_back(&sink.0.borrow()), records);
+}
+
+/// PUSH r1, POP r2
+const PUSH_POP: [u8; 4] = [0xA2, 0x06, 0xA0, 0x0A];
+const STACK: u32 = 0x800;
+
+#[test]
+fn stack_accesses_are_traced_as_push_and_pop() {
+    let sink = Shared::default();
+    let mut cpu = Cpu::with_memory(0x1000);
+    cpu.load_image(&PUSH_POP, 0).unwrap();
+    cpu.reg[15] = STACK;
+    cpu.trace = Some(TraceWriter::new(sink.clone()).unwrap());
+
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    cpu.trace.take().unwrap().finish().unwrap();
+
+    let records = read_back(&sink.0.borrow());
+    let data: Vec<_> = records.iter().filter(|r| r.kind != TraceKind::Fetch).collect();
+
+    assert_eq!(data.len(), 2);
+    assert_eq!((data[0].kind, data[0].va, data[0].width), (TraceKind::Push, STACK - 4, 4));
+    assert_eq!((data[1].kind, data[1].va, data[1].width), (TraceKind::Pop, STACK - 4, 4));
+    assert_eq!(data[1].instruction, data[0].instruction + 1);
+}
+
+#[test]
+fn bad_headers_and_kinds_are_rejected() {
+    assert!(TraceReader::new(&b"VTRX\x01"[..]).is_err());
+    assert!(TraceReader::new(&b"VTRC\x02"[..]).is_err());
+    assert!(TraceReader::new(&b"VTR"[..]).is_err());
+
+    let mut bytes = b"VTRC\x01".to_vec();
+    bytes.extend_from_slice(&[0; 17]);
+    bytes.push(5);
+
+    let mut reader = TraceReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+}